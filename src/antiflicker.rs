@@ -0,0 +1,153 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use core::str::FromStr;
+
+use crate::display::{Hotkey, Renderer, Status, DIMMED};
+use crate::framebuffer::FrameBuffer;
+use crate::keypad::Keypad;
+
+/// Most frames that can be combined
+pub const MAX_FRAMES: usize = 8;
+
+/// How the last frames are combined before they are presented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AntiFlicker {
+    /// A pixel is lit if it was in any of the last n frames
+    Or(usize),
+    /// Pixels lit in the last frame keep their color, and the ones only lit
+    /// in the n - 1 frames before it are shown dimmed
+    Blend(usize),
+}
+
+impl AntiFlicker {
+    /// Number of frames combined
+    pub fn frames(&self) -> usize {
+        match *self {
+            AntiFlicker::Or(n) | AntiFlicker::Blend(n) => n,
+        }
+    }
+
+    /// Combines a pixel of the last frame with the same one of an older frame
+    fn combine(&self, pixel: u8, older: u8) -> u8 {
+        match self {
+            AntiFlicker::Or(_) => pixel | older,
+            AntiFlicker::Blend(_) if pixel == 0 && older != 0 => older | DIMMED,
+            AntiFlicker::Blend(_) => pixel,
+        }
+    }
+}
+
+impl FromStr for AntiFlicker {
+    type Err = String;
+
+    /// Parses `or` or `blend`, with the number of frames after a colon, 2 by
+    /// default
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.to_lowercase();
+        let (mode, frames) = match lower.split_once(':') {
+            Some((mode, frames)) => (mode, Some(frames)),
+            None => (lower.as_str(), None),
+        };
+        let frames = match frames {
+            Some(n) => n
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_FRAMES).contains(n))
+                .ok_or_else(|| {
+                    format!("Frames of {} must be from 1 to {}", s, MAX_FRAMES)
+                })?,
+            None => 2,
+        };
+        match mode {
+            "or" => Ok(AntiFlicker::Or(frames)),
+            "blend" => Ok(AntiFlicker::Blend(frames)),
+            _ => Err(format!("Unknown anti-flicker mode {}", s)),
+        }
+    }
+}
+
+impl Default for AntiFlicker {
+    fn default() -> Self {
+        AntiFlicker::Or(2)
+    }
+}
+
+/// A renderer presenting the last frames combined on another renderer
+///
+/// Only what is presented changes, the frame of Chip8 that collisions are
+/// checked against is left as is
+pub struct FrameBlender<R: Renderer> {
+    pub inner: R,
+    mode: AntiFlicker,
+    /// The last frames rendered, the newest at the back
+    history: VecDeque<FrameBuffer>,
+    blended: FrameBuffer,
+}
+
+impl<R: Renderer> FrameBlender<R> {
+    pub fn new(inner: R, mode: AntiFlicker) -> Self {
+        Self {
+            inner,
+            mode,
+            history: VecDeque::with_capacity(MAX_FRAMES),
+            blended: FrameBuffer::new(),
+        }
+    }
+}
+
+impl<R: Renderer> Renderer for FrameBlender<R> {
+    fn render(&mut self, frame: &FrameBuffer) {
+        // Frames of another resolution can't be combined
+        if self.history.back().map(FrameBuffer::size) != Some(frame.size()) {
+            self.history.clear();
+        }
+        if self.history.len() >= self.mode.frames().clamp(1, MAX_FRAMES) {
+            self.history.pop_front();
+        }
+        self.history.push_back(frame.clone());
+
+        self.blended.clone_from(frame);
+        let (width, height) = frame.size();
+        for older in self.history.iter().rev().skip(1) {
+            for y in 0..height {
+                for x in 0..width {
+                    let pixel = self.blended.get_pixel(x, y);
+                    let pixel = self.mode.combine(pixel, older.get_pixel(x, y));
+                    self.blended.set_pixel(x, y, pixel);
+                }
+            }
+        }
+        self.inner.render(&self.blended);
+    }
+
+    fn status(&mut self, status: &Status) {
+        self.inner.status(status);
+    }
+
+    fn message(&mut self, message: &str) {
+        self.inner.message(message);
+    }
+
+    fn paced(&self) -> bool {
+        self.inner.paced()
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn poll_keys(&mut self, keypad: &mut dyn Keypad) {
+        self.inner.poll_keys(keypad);
+    }
+
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        self.inner.poll_hotkey()
+    }
+}
+
+impl<R: Renderer + Default> Default for FrameBlender<R> {
+    fn default() -> Self {
+        Self::new(R::default(), AntiFlicker::default())
+    }
+}
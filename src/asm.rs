@@ -0,0 +1,424 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::disasm::ORIGIN;
+use crate::memory::XO_MEMORY_SIZE;
+use crate::opcode::{Instruction, Reg};
+
+/// Names that can't be used for labels and constants
+const RESERVED: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "LONG"];
+
+/// An error in the assembly source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// Line of the source where the error is, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// An operand of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    /// VX
+    V(Reg),
+    /// VX-VY
+    Range(Reg, Reg),
+    I,
+    /// [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long,
+    /// A number, label or constant
+    Value(i32),
+}
+
+/// An instruction or directive waiting for the labels to be known
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles source using the mnemonics printed by the debug output into a
+/// ROM loaded at ORIGIN
+///
+/// Besides instructions, each line can have a `label:`, a `NAME = value`
+/// constant, `db` bytes or sprite rows such as `db "##..##.."`, `dw` words
+/// and a `;` comment
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler::new();
+    let statements = assembler.labels(source)?;
+
+    let mut rom = Vec::new();
+    for statement in statements {
+        assembler
+            .statement(&statement, &mut rom)
+            .map_err(|message| AsmError {
+                line: statement.line,
+                message,
+            })?;
+    }
+    Ok(rom)
+}
+
+struct Assembler {
+    /// Values of the labels and constants
+    symbols: HashMap<String, i32>,
+}
+
+impl Assembler {
+    fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// First pass, defines the labels and constants and returns the
+    /// statements to assemble
+    fn labels<'a>(
+        &mut self,
+        source: &'a str,
+    ) -> Result<Vec<Statement<'a>>, AsmError> {
+        let mut statements = Vec::new();
+        let mut addr = ORIGIN as usize;
+
+        for (i, line) in source.lines().enumerate() {
+            let error = |message| AsmError {
+                line: i + 1,
+                message,
+            };
+            let mut code = line.split(';').next().unwrap_or("").trim();
+
+            if let Some(colon) = code.find(':') {
+                self.define(&code[..colon], addr as i32).map_err(error)?;
+                code = code[colon + 1..].trim();
+            }
+            if let Some(equals) = code.find('=') {
+                let value = self.value(&code[equals + 1..]).map_err(error)?;
+                self.define(&code[..equals], value).map_err(error)?;
+                continue;
+            }
+            if code.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, operands) = match code.find(char::is_whitespace) {
+                Some(space) => (&code[..space], code[space..].trim()),
+                None => (code, ""),
+            };
+            let statement = Statement {
+                line: i + 1,
+                mnemonic: mnemonic.to_uppercase(),
+                operands: if operands.is_empty() {
+                    Vec::new()
+                } else {
+                    operands.split(',').map(str::trim).collect()
+                },
+            };
+
+            addr += match statement.mnemonic.as_str() {
+                "DB" => statement.operands.iter().map(|s| data_size(s)).sum(),
+                "DW" => 2 * statement.operands.len(),
+                _ => 2,
+            };
+            if addr > XO_MEMORY_SIZE {
+                return Err(error("Program doesn't fit in memory".to_string()));
+            }
+            statements.push(statement);
+        }
+
+        Ok(statements)
+    }
+
+    /// Defines a label or constant
+    fn define(&mut self, name: &str, value: i32) -> Result<(), String> {
+        let name = name.trim();
+        let valid = name
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let upper = name.to_uppercase();
+        if !valid || register(&upper).is_some() || RESERVED.contains(&&*upper) {
+            return Err(format!("Invalid name {}", name));
+        }
+        if self.symbols.insert(name.to_string(), value).is_some() {
+            return Err(format!("{} is already defined", name));
+        }
+        Ok(())
+    }
+
+    /// Second pass, assembles a statement at the end of the rom
+    fn statement(
+        &self,
+        statement: &Statement,
+        rom: &mut Vec<u8>,
+    ) -> Result<(), String> {
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in &statement.operands {
+                    match operand.strip_prefix('"') {
+                        Some(sprite) => rom.extend(sprite_row(sprite)?),
+                        None => rom.push(byte(self.value(operand)?)?),
+                    }
+                }
+            }
+            "DW" => {
+                for operand in &statement.operands {
+                    rom.extend(&word(self.value(operand)?)?.to_be_bytes());
+                }
+            }
+            mnemonic => {
+                let operands = statement
+                    .operands
+                    .iter()
+                    .map(|operand| self.operand(operand))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let opcode = instruction(mnemonic, &operands)?.encode();
+                rom.extend(&opcode.to_be_bytes());
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an operand of an instruction
+    fn operand(&self, s: &str) -> Result<Operand, String> {
+        use Operand::*;
+
+        let upper = s.to_uppercase();
+        let operand = match upper.as_str() {
+            "I" => I,
+            "[I]" => IndirectI,
+            "DT" => Dt,
+            "ST" => St,
+            "K" => K,
+            "F" => F,
+            "HF" => Hf,
+            "B" => B,
+            "R" => R,
+            "LONG" => Long,
+            _ => match register(&upper) {
+                Some(x) => V(x),
+                None => match register_range(&upper) {
+                    Some((x, y)) => Range(x, y),
+                    None => Value(self.value(s)?),
+                },
+            },
+        };
+        Ok(operand)
+    }
+
+    /// Evaluates a sum of numbers, labels and constants
+    fn value(&self, expr: &str) -> Result<i32, String> {
+        let mut rest = expr.trim();
+        let mut sign = 1;
+        if let Some(negated) = rest.strip_prefix('-') {
+            sign = -1;
+            rest = negated;
+        }
+
+        let mut total = 0i32;
+        loop {
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            total = total.wrapping_add(
+                self.term(rest[..end].trim())?.wrapping_mul(sign),
+            );
+            if end == rest.len() {
+                return Ok(total);
+            }
+            sign = if rest[end..].starts_with('+') { 1 } else { -1 };
+            rest = &rest[end + 1..];
+        }
+    }
+
+    /// Evaluates a number, label or constant
+    fn term(&self, term: &str) -> Result<i32, String> {
+        let number = |digits: &str, radix| {
+            i32::from_str_radix(digits, radix)
+                .map_err(|_| format!("Invalid number {}", term))
+        };
+
+        if term.is_empty() {
+            Err("Missing value".to_string())
+        } else if let Some(hex) =
+            term.strip_prefix("0x").or_else(|| term.strip_prefix("0X"))
+        {
+            number(hex, 16)
+        } else if let Some(bin) =
+            term.strip_prefix("0b").or_else(|| term.strip_prefix("0B"))
+        {
+            number(bin, 2)
+        } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+            number(term, 10)
+        } else {
+            self.symbols
+                .get(term)
+                .copied()
+                .ok_or_else(|| format!("Unknown symbol {}", term))
+        }
+    }
+}
+
+/// Builds the instruction for a mnemonic and its operands
+fn instruction(
+    mnemonic: &str,
+    operands: &[Operand],
+) -> Result<Instruction, String> {
+    use Instruction::*;
+    use Operand::*;
+
+    let instruction = match (mnemonic, operands) {
+        ("NOP", []) => Nop,
+        ("SYS", [Value(a)]) => Sys(addr(*a)?),
+        ("SCD", [Value(n)]) => Scd(nibble(*n)?),
+        ("SCU", [Value(n)]) => Scu(nibble(*n)?),
+        ("CLS", []) => Cls,
+        ("RET", []) => Ret,
+        ("SCR", []) => Scr,
+        ("SCL", []) => Scl,
+        ("EXIT", []) => Exit,
+        ("LOW", []) => Low,
+        ("HIGH", []) => High,
+        ("JP", [Value(a)]) => Jp(addr(*a)?),
+        ("JP", [V(0), Value(a)]) => JpV0(addr(*a)?),
+        ("CALL", [Value(a)]) => Call(addr(*a)?),
+        ("SE", [V(x), Value(n)]) => SeVxByte(*x, byte(*n)?),
+        ("SE", [V(x), V(y)]) => SeVxVy(*x, *y),
+        ("SNE", [V(x), Value(n)]) => SneVxByte(*x, byte(*n)?),
+        ("SNE", [V(x), V(y)]) => SneVxVy(*x, *y),
+        ("ADD", [V(x), Value(n)]) => AddVxByte(*x, byte(*n)?),
+        ("ADD", [V(x), V(y)]) => AddVxVy(*x, *y),
+        ("ADD", [I, V(x)]) => AddIVx(*x),
+        ("OR", [V(x), V(y)]) => Or(*x, *y),
+        ("AND", [V(x), V(y)]) => And(*x, *y),
+        ("XOR", [V(x), V(y)]) => Xor(*x, *y),
+        ("SUB", [V(x), V(y)]) => Sub(*x, *y),
+        ("SUBN", [V(x), V(y)]) => Subn(*x, *y),
+        ("SHR", [V(x)]) => Shr(*x, *x),
+        ("SHR", [V(x), V(y)]) => Shr(*x, *y),
+        ("SHL", [V(x)]) => Shl(*x, *x),
+        ("SHL", [V(x), V(y)]) => Shl(*x, *y),
+        ("RND", [V(x), Value(n)]) => Rnd(*x, byte(*n)?),
+        ("DRW", [V(x), V(y), Value(n)]) => Drw(*x, *y, nibble(*n)?),
+        ("SKP", [V(x)]) => Skp(*x),
+        ("SKNP", [V(x)]) => Sknp(*x),
+        ("PLANE", [Value(n)]) => Plane(nibble(*n)?),
+        ("AUDIO", []) => Audio,
+        ("PITCH", [V(x)]) => Pitch(*x),
+        ("LD", [V(x), Value(n)]) => LdVxByte(*x, byte(*n)?),
+        ("LD", [V(x), V(y)]) => LdVxVy(*x, *y),
+        ("LD", [I, Value(a)]) => LdI(addr(*a)?),
+        ("LD", [I, Long]) => LdILong,
+        ("LD", [IndirectI, Range(x, y)]) => LdRangeIVx(*x, *y),
+        ("LD", [Range(x, y), IndirectI]) => LdRangeVxI(*x, *y),
+        ("LD", [V(x), Dt]) => LdVxDt(*x),
+        ("LD", [V(x), K]) => LdVxK(*x),
+        ("LD", [Dt, V(x)]) => LdDtVx(*x),
+        ("LD", [St, V(x)]) => LdStVx(*x),
+        ("LD", [F, V(x)]) => LdFVx(*x),
+        ("LD", [Hf, V(x)]) => LdHfVx(*x),
+        ("LD", [B, V(x)]) => LdBVx(*x),
+        ("LD", [IndirectI, V(x)]) => LdIVx(*x),
+        ("LD", [V(x), IndirectI]) => LdVxI(*x),
+        ("LD", [R, V(x)]) => LdRVx(*x),
+        ("LD", [V(x), R]) => LdVxR(*x),
+        _ if is_mnemonic(mnemonic) => {
+            return Err(format!("Invalid operands for {}", mnemonic))
+        }
+        _ => return Err(format!("Unknown instruction {}", mnemonic)),
+    };
+    Ok(instruction)
+}
+
+/// Whether the mnemonic names an instruction
+fn is_mnemonic(mnemonic: &str) -> bool {
+    [
+        "NOP", "SYS", "SCD", "SCU", "CLS", "RET", "SCR", "SCL", "EXIT", "LOW",
+        "HIGH", "JP", "CALL", "SE", "SNE", "ADD", "OR", "AND", "XOR", "SUB",
+        "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP", "PLANE", "AUDIO",
+        "PITCH", "LD",
+    ]
+    .contains(&mnemonic)
+}
+
+/// Parses an uppercase register name such as VA
+fn register(s: &str) -> Option<Reg> {
+    let x = s.strip_prefix('V')?;
+    if x.len() == 1 {
+        Reg::from_str_radix(x, 16).ok()
+    } else {
+        None
+    }
+}
+
+/// Parses an uppercase register range such as V1-V4
+fn register_range(s: &str) -> Option<(Reg, Reg)> {
+    let dash = s.find('-')?;
+    Some((register(&s[..dash])?, register(&s[dash + 1..])?))
+}
+
+/// Size in bytes of a db operand
+fn data_size(operand: &str) -> usize {
+    match operand.strip_prefix('"') {
+        Some(sprite) => sprite.trim_end_matches('"').len().div_ceil(8),
+        None => 1,
+    }
+}
+
+/// Parses a quoted row of sprite pixels, 8 pixels per byte
+fn sprite_row(sprite: &str) -> Result<Vec<u8>, String> {
+    let pixels = sprite
+        .strip_suffix('"')
+        .ok_or_else(|| "Unterminated sprite literal".to_string())?;
+    let mut bytes = vec![0; pixels.len().div_ceil(8)];
+    for (i, pixel) in pixels.chars().enumerate() {
+        match pixel {
+            '#' | '1' => bytes[i / 8] |= 0x80 >> (i % 8),
+            '.' | '0' => {}
+            _ => return Err(format!("Invalid sprite pixel {}", pixel)),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Checks that a value is an address
+fn addr(value: i32) -> Result<u16, String> {
+    match value {
+        0..=0xFFF => Ok(value as u16),
+        _ => Err(format!("Address {:#X} out of range", value)),
+    }
+}
+
+/// Checks that a value fits in a nibble
+fn nibble(value: i32) -> Result<u8, String> {
+    match value {
+        0..=0xF => Ok(value as u8),
+        _ => Err(format!("{} doesn't fit in 4 bits", value)),
+    }
+}
+
+/// Checks that a value fits in a byte, negative values are two's complement
+fn byte(value: i32) -> Result<u8, String> {
+    match value {
+        -0x80..=0xFF => Ok(value as u8),
+        _ => Err(format!("{} doesn't fit in 8 bits", value)),
+    }
+}
+
+/// Checks that a value fits in a word, negative values are two's complement
+fn word(value: i32) -> Result<u16, String> {
+    match value {
+        -0x8000..=0xFFFF => Ok(value as u16),
+        _ => Err(format!("{} doesn't fit in 16 bits", value)),
+    }
+}
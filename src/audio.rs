@@ -0,0 +1,261 @@
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::{self, BufWriter, Write};
+#[cfg(feature = "std")]
+use std::path::Path;
+
+#[cfg(feature = "std")]
+use crate::chip8::TIMER_HZ;
+
+/// Sample rate of the rendered audio
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Default frequency of the buzzer
+pub const BEEP_HZ: u32 = 440;
+
+/// Size of the XO-CHIP audio pattern buffer, played one bit at a time
+pub const PATTERN_SIZE: usize = 16;
+
+/// Default XO-CHIP pitch, which plays the pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// The buzzer of the CHIP-8, which sounds while the sound timer is not zero
+pub trait Audio {
+    /// Turns the buzzer on or off
+    fn beep(&mut self, on: bool);
+    /// Advances the audio by one 60 Hz frame
+    fn frame(&mut self);
+
+    /// Sets the XO-CHIP audio pattern played by the buzzer
+    /// Audios that can't play patterns keep their own tone
+    fn pattern(&mut self, _pattern: &[u8; PATTERN_SIZE]) {}
+    /// Sets the XO-CHIP pitch of the audio pattern
+    fn pitch(&mut self, _pitch: u8) {}
+}
+
+/// Bits per second played from an XO-CHIP audio pattern at pitch
+#[cfg(feature = "std")]
+pub fn pattern_rate(pitch: u8) -> f64 {
+    4000.0 * 2f64.powf((pitch as f64 - 64.0) / 48.0)
+}
+
+/// An audio that produces no sound, but keeps track of when the buzzer sounded
+#[derive(Debug)]
+pub struct NullAudio {
+    /// Frames elapsed so far
+    pub frames: u64,
+    /// Every beep as (first frame, number of frames)
+    pub beeps: Vec<(u64, u64)>,
+    on: bool,
+}
+
+impl NullAudio {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            beeps: Vec::new(),
+            on: false,
+        }
+    }
+}
+
+impl Audio for NullAudio {
+    fn beep(&mut self, on: bool) {
+        if on && !self.on {
+            self.beeps.push((self.frames, 0));
+        }
+        self.on = on;
+    }
+
+    fn frame(&mut self) {
+        if self.on {
+            if let Some(beep) = self.beeps.last_mut() {
+                beep.1 += 1;
+            }
+        }
+        self.frames += 1;
+    }
+}
+
+impl Default for NullAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An audio that rings the terminal bell whenever the buzzer starts
+#[cfg(feature = "std")]
+pub struct TerminalBell {
+    on: bool,
+}
+
+#[cfg(feature = "std")]
+impl TerminalBell {
+    pub fn new() -> Self {
+        Self { on: false }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Audio for TerminalBell {
+    fn beep(&mut self, on: bool) {
+        if on && !self.on {
+            eprint!("\x07");
+        }
+        self.on = on;
+    }
+
+    fn frame(&mut self) {}
+}
+
+#[cfg(feature = "std")]
+impl Default for TerminalBell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An audio that renders the buzzer as a square wave to a sample buffer
+#[cfg(feature = "std")]
+pub struct SquareWave {
+    /// Rendered 16 bit mono samples
+    samples: Vec<i16>,
+    /// Frequency of the square wave
+    frequency: u32,
+    /// Amplitude of the square wave
+    volume: i16,
+    /// Samples rendered since the buzzer was turned on
+    phase: u32,
+    /// XO-CHIP audio pattern, which replaces the square wave once set
+    pattern: Option<[u8; PATTERN_SIZE]>,
+    /// XO-CHIP pitch of the audio pattern
+    pitch: u8,
+    /// Position in bits of the audio pattern
+    pattern_pos: f64,
+    on: bool,
+}
+
+#[cfg(feature = "std")]
+impl SquareWave {
+    pub fn new() -> Self {
+        Self {
+            samples: Vec::new(),
+            frequency: BEEP_HZ,
+            volume: i16::MAX / 4,
+            phase: 0,
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            pattern_pos: 0.0,
+            on: false,
+        }
+    }
+
+    /// Sets the frequency of the square wave
+    pub fn frequency(mut self, frequency: u32) -> Self {
+        self.frequency = frequency.max(1);
+        self
+    }
+
+    /// Gets the samples rendered so far
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Renders a frame of the XO-CHIP audio pattern, looping over its bits
+    fn pattern_frame(&mut self, pattern: &[u8; PATTERN_SIZE]) {
+        let bits = (PATTERN_SIZE * 8) as f64;
+        let step = pattern_rate(self.pitch) / SAMPLE_RATE as f64;
+        for _ in 0..SAMPLE_RATE / TIMER_HZ {
+            let bit = self.pattern_pos as usize;
+            let sample = if !self.on {
+                0
+            } else if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.samples.push(sample);
+            if self.on {
+                self.pattern_pos = (self.pattern_pos + step) % bits;
+            }
+        }
+    }
+
+    /// Writes the samples rendered so far as a WAV file
+    pub fn write_wav<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let data_size = (self.samples.len() * 2) as u32;
+
+        w.write_all(b"RIFF")?;
+        w.write_all(&(36 + data_size).to_le_bytes())?;
+        w.write_all(b"WAVE")?;
+
+        w.write_all(b"fmt ")?;
+        w.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?;
+        w.write_all(&SAMPLE_RATE.to_le_bytes())?;
+        // Byte rate, block align and bits per sample
+        w.write_all(&(SAMPLE_RATE * 2).to_le_bytes())?;
+        w.write_all(&2u16.to_le_bytes())?;
+        w.write_all(&16u16.to_le_bytes())?;
+
+        w.write_all(b"data")?;
+        w.write_all(&data_size.to_le_bytes())?;
+        for sample in &self.samples {
+            w.write_all(&sample.to_le_bytes())?;
+        }
+
+        w.flush()
+    }
+
+    /// Saves the samples rendered so far to a WAV file at path
+    pub fn save_wav<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.write_wav(BufWriter::new(File::create(path)?))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Audio for SquareWave {
+    fn beep(&mut self, on: bool) {
+        if on && !self.on {
+            self.phase = 0;
+        }
+        self.on = on;
+    }
+
+    fn frame(&mut self) {
+        if let Some(pattern) = self.pattern {
+            self.pattern_frame(&pattern);
+            return;
+        }
+
+        let half_period = (SAMPLE_RATE / self.frequency / 2).max(1);
+        for _ in 0..SAMPLE_RATE / TIMER_HZ {
+            let sample = match (self.on, (self.phase / half_period) % 2) {
+                (false, _) => 0,
+                (true, 0) => self.volume,
+                (true, _) => -self.volume,
+            };
+            self.samples.push(sample);
+            self.phase = self.phase.wrapping_add(1);
+        }
+    }
+
+    fn pattern(&mut self, pattern: &[u8; PATTERN_SIZE]) {
+        self.pattern = Some(*pattern);
+    }
+
+    fn pitch(&mut self, pitch: u8) {
+        self.pitch = pitch;
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SquareWave {
+    fn default() -> Self {
+        Self::new()
+    }
+}
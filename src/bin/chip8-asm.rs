@@ -0,0 +1,38 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use chip8_emulator::asm::assemble;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: chip8-asm <source> [rom]");
+        process::exit(1);
+    }
+    let file_name = &args[1];
+    let output = match args.get(2) {
+        Some(output) => Path::new(output).to_path_buf(),
+        None => Path::new(file_name).with_extension("ch8"),
+    };
+
+    let source = match fs::read_to_string(file_name) {
+        Ok(source) => source,
+        Err(_) => {
+            eprintln!("Could not read file {}", file_name);
+            process::exit(1);
+        }
+    };
+    let rom = match assemble(&source) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("{}: {}", file_name, error);
+            process::exit(1);
+        }
+    };
+    if fs::write(&output, &rom).is_err() {
+        eprintln!("Could not write file {}", output.display());
+        process::exit(1);
+    }
+}
@@ -0,0 +1,68 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::process;
+
+use chip8_emulator::chip8::{Chip8, Platform};
+use chip8_emulator::debugger::Debugger;
+use chip8_emulator::display::NullRenderer;
+use chip8_emulator::quirks::Quirks;
+
+const USAGE: &str = "\
+Usage: chip8-debug <rom> [options]
+
+Options:
+    --quirks <preset>   vip, chip48, schip or modern, modern by default
+    --platform <name>   chip8, schip or xochip, chip8 by default";
+
+/// Prints an error and exits
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut file_name = None;
+    let mut quirks = Quirks::default();
+    let mut platform = Platform::Chip8;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("Missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--quirks" => {
+                quirks = value().parse().unwrap_or_else(|e: String| fail(&e))
+            }
+            "--platform" => {
+                platform = value().parse().unwrap_or_else(|e: String| fail(&e))
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            _ if file_name.is_none() && !arg.starts_with('-') => {
+                file_name = Some(arg)
+            }
+            _ => fail(&format!("Unexpected argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+    let file_name = file_name.unwrap_or_else(|| fail(USAGE));
+    let data = match fs::read(&file_name) {
+        Ok(data) => data,
+        Err(_) => fail(&format!("Could not read file {}", file_name)),
+    };
+
+    // The display is printed on demand by the screen command
+    let mut chip8 = Chip8::<NullRenderer>::new(quirks).platform(platform);
+    if let Err(e) = chip8.load_rom(&data) {
+        fail(&format!("Could not load {}: {}", file_name, e));
+    }
+
+    let stdin = io::stdin();
+    let mut debugger = Debugger::new(chip8);
+    if let Err(e) = debugger.repl(stdin.lock(), io::stdout()) {
+        fail(&e.to_string());
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::fs;
+use std::process;
+
+use chip8_emulator::disasm::Disassembly;
+
+fn main() {
+    let file_name = match env::args().nth(1) {
+        Some(file_name) => file_name,
+        None => {
+            eprintln!("Usage: chip8-disasm <rom>");
+            process::exit(1);
+        }
+    };
+    let data = match fs::read(&file_name) {
+        Ok(data) => data,
+        Err(_) => {
+            eprintln!("Could not read file {}", file_name);
+            process::exit(1);
+        }
+    };
+
+    print!("{}", Disassembly::new(&data));
+}
@@ -0,0 +1,130 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::process;
+
+use chip8_emulator::chip8::{Chip8, Platform};
+use chip8_emulator::display::NullRenderer;
+use chip8_emulator::headless::{parse_script, registers_json, run, Limit};
+use chip8_emulator::image::{write_pbm, write_png};
+use chip8_emulator::quirks::Quirks;
+use chip8_emulator::trace::{TraceFormat, WriteTracer};
+
+const USAGE: &str = "\
+Usage: chip8-headless <rom> (--frames <n> | --cycles <n>) [options]
+
+Options:
+    --input <file>      press and release keys following a script
+    --png <file>        write the final display as a PNG
+    --pbm <file>        write the final display as a PBM
+    --json <file>       write the registers as JSON, instead of printing them
+    --trace <file>      write every executed instruction to a file
+    --trace-format <f>  text, csv or jsonl, text by default
+    --seed <n>          seed of the random number generator, 0 by default
+    --platform <name>   chip8, schip or xochip";
+
+/// Prints an error and exits
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut rom = None;
+    let mut limit = None;
+    let mut input = None;
+    let mut png = None;
+    let mut pbm = None;
+    let mut json = None;
+    let mut trace = None;
+    let mut trace_format = TraceFormat::Text;
+    let mut seed = 0;
+    let mut platform = Platform::Chip8;
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(&format!("Missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--frames" | "--cycles" => {
+                let n = value()
+                    .parse()
+                    .unwrap_or_else(|_| fail(&format!("Invalid {}", arg)));
+                limit = Some(match arg.as_str() {
+                    "--frames" => Limit::Frames(n),
+                    _ => Limit::Cycles(n),
+                });
+            }
+            "--input" => input = Some(value()),
+            "--png" => png = Some(value()),
+            "--pbm" => pbm = Some(value()),
+            "--json" => json = Some(value()),
+            "--trace" => trace = Some(value()),
+            "--trace-format" => {
+                trace_format =
+                    value().parse().unwrap_or_else(|e: String| fail(&e))
+            }
+            "--seed" => {
+                seed = value().parse().unwrap_or_else(|_| fail("Invalid seed"))
+            }
+            "--platform" => {
+                platform = value().parse().unwrap_or_else(|e: String| fail(&e))
+            }
+            _ if rom.is_none() && !arg.starts_with("--") => rom = Some(arg),
+            _ => fail(USAGE),
+        }
+    }
+    let (rom, limit) = match (rom, limit) {
+        (Some(rom), Some(limit)) => (rom, limit),
+        _ => fail(USAGE),
+    };
+
+    let data = fs::read(&rom)
+        .unwrap_or_else(|_| fail(&format!("Could not read file {}", rom)));
+    let inputs = match input {
+        Some(input) => {
+            let script = fs::read_to_string(&input).unwrap_or_else(|_| {
+                fail(&format!("Could not read file {}", input))
+            });
+            parse_script(&script)
+                .unwrap_or_else(|e| fail(&format!("{}: {}", input, e)))
+        }
+        None => Vec::new(),
+    };
+
+    let create = |path: &str| {
+        File::create(path).map(BufWriter::new).unwrap_or_else(|_| {
+            fail(&format!("Could not create file {}", path))
+        })
+    };
+
+    let mut chip8 = Chip8::<NullRenderer>::new(Quirks::default())
+        .platform(platform)
+        .seed(seed);
+    if let Some(path) = trace {
+        chip8 =
+            chip8.tracer(WriteTracer::with_format(create(&path), trace_format));
+    }
+    chip8
+        .load_rom(&data)
+        .unwrap_or_else(|e| fail(&format!("Could not load {}: {}", rom, e)));
+    let progress = run(&mut chip8, limit, &inputs);
+
+    if let Some(path) = png {
+        write_png(&chip8.frame, create(&path))
+            .unwrap_or_else(|_| fail(&format!("Could not write {}", path)));
+    }
+    if let Some(path) = pbm {
+        write_pbm(&chip8.frame, create(&path))
+            .unwrap_or_else(|_| fail(&format!("Could not write {}", path)));
+    }
+
+    let registers = registers_json(&chip8, progress);
+    match json {
+        Some(path) => fs::write(&path, registers)
+            .unwrap_or_else(|_| fail(&format!("Could not write {}", path))),
+        None => print!("{}", registers),
+    }
+}
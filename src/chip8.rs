@@ -1,552 +1,1034 @@
-use rand::prelude::{thread_rng, Rng, ThreadRng};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use std::fs;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
 
+use crate::audio::{Audio, NullAudio, PATTERN_SIZE};
 use crate::cpu::Cpu;
-use crate::display::Display;
-use crate::memory::Memory;
-use crate::opcode::{Nibble, Opcode};
-use crate::stack::Stack;
+#[cfg(feature = "std")]
+use crate::display::Hotkey;
+use crate::display::{
+    NullRenderer, Renderer, Status, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH,
+};
+use crate::error::Chip8Error;
+use crate::framebuffer::FrameBuffer;
+use crate::keypad::{HexKeypad, Keypad};
+use crate::memory::{Memory, MEMORY_SIZE, PROGRAM_START, XO_MEMORY_SIZE};
+#[cfg(feature = "std")]
+use crate::opcode::DecodeError;
+use crate::opcode::{decode, Instruction, Reg};
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::random::{Random, SplitMix};
+#[cfg(feature = "std")]
+use crate::rewind::Rewind;
+use crate::sprites::BIG_SPRITES_OFFSET;
+use crate::stack::{Stack, STACK_SIZE};
+use crate::state::{StateError, StateReader, StateWriter};
+#[cfg(feature = "std")]
+use crate::trace::WriteTracer;
+use crate::trace::{Step, Tracer};
 
-pub struct Chip8<D: Display + Default> {
+/// Frequency of the delay and sound timers, which is also the frame rate
+pub const TIMER_HZ: u32 = 60;
+
+/// Default number of instructions executed per second
+pub const DEFAULT_SPEED: u32 = 700;
+
+/// The longest the emulator tries to catch up after falling behind
+#[cfg(feature = "std")]
+const MAX_LAG: Duration = Duration::from_millis(250);
+
+/// Number of RPL user flags of the SUPER-CHIP
+const RPL_FLAGS: usize = 8;
+
+/// Number of RPL user flags of the XO-CHIP
+const XO_RPL_FLAGS: usize = 16;
+
+/// The instruction set understood by the interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    /// The original CHIP-8
+    Chip8,
+    /// SUPER-CHIP 1.1, with scrolling and a 128x64 high resolution mode
+    SuperChip,
+    /// XO-CHIP, extends SUPER-CHIP with 64 KiB of memory, two bitplanes and
+    /// audio patterns
+    XoChip,
+}
+
+impl Platform {
+    /// Addressable memory of the platform
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// Largest ROM that fits in the memory of the platform
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - PROGRAM_START as usize
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "chip8" => Ok(Platform::Chip8),
+            "schip" => Ok(Platform::SuperChip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!("Unknown platform {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Platform::Chip8 => write!(f, "chip8"),
+            Platform::SuperChip => write!(f, "schip"),
+            Platform::XoChip => write!(f, "xochip"),
+        }
+    }
+}
+
+/// What a cycle did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction was executed
+    Executed(Instruction),
+    /// The opcode isn't an instruction of the platform, so it was skipped
+    Skipped(u16),
+    /// 0xFX0A is waiting for a key, so PC stays on it
+    WaitingKey,
+    /// The program exited with 0x00FD
+    Exited,
+}
+
+pub struct Chip8<
+    D: Renderer + Default = NullRenderer,
+    K: Keypad + Default = HexKeypad,
+    A: Audio + Default = NullAudio,
+    R: Random + Default = SplitMix,
+> {
     /// Memory
     pub mem: Memory,
     /// CPU
     pub cpu: Cpu,
     /// Call stack
     pub stack: Stack,
-    /// The emulator's display
+    /// The pixels drawn by the program
+    pub frame: FrameBuffer,
+    /// Presents the frames, with the keys and hotkeys as input
     pub display: D,
-    /// A random number generator provided by Rust
-    pub rng: ThreadRng,
+    /// The emulator's keypad
+    pub keypad: K,
+    /// The emulator's buzzer
+    pub audio: A,
+    /// The source of random numbers for 0xCXNN
+    pub rng: R,
     /// Delay timer
     delay_timer: u8,
     /// Sound timer
     sound_timer: u8,
+    /// SUPER-CHIP RPL user flags, stored by 0xFX75
+    rpl: [u8; XO_RPL_FLAGS],
+    /// Instruction set of the interpreter
+    platform: Platform,
+    /// Behavior of the ambiguous opcodes
+    quirks: Quirks,
+    /// If frames are sent to the display
+    render: bool,
+    /// If a sprite was drawn this frame, used by the display wait quirk
+    drawn: bool,
+    /// If the program exited with 0x00FD
+    exited: bool,
+    /// Instructions executed per second
+    speed: u32,
+    /// Accumulates the instructions that don't fit evenly in a frame
+    speed_remainder: u32,
+    /// If the CPU is blocked waiting for a key by 0xFX0A
+    waiting_key: bool,
+    /// Path the quick save slots are saved next to
+    #[cfg(feature = "std")]
+    state_path: Option<PathBuf>,
+    /// Selected quick save slot
+    #[cfg(feature = "std")]
+    slot: u8,
+    /// Receives the executed instructions
+    tracer: Option<Box<dyn Tracer>>,
     /// Useful debugging information
+    #[cfg(feature = "std")]
     debug: bool,
 }
 
-impl<D> Chip8<D>
+impl<D, K, A, R> Chip8<D, K, A, R>
 where
-    D: Display + Default,
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
 {
-    /// Creates a new Chip8
-    pub fn new() -> Self {
+    /// Creates a new Chip8 with the quirks of an interpreter
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_display(quirks, D::default())
+    }
+
+    /// Creates a new Chip8 drawing to display
+    pub fn with_display(quirks: Quirks, display: D) -> Self {
         Self {
             mem: Memory::new(),
             cpu: Cpu::new(),
             stack: Stack::new(),
-            display: D::default(),
-            rng: thread_rng(),
+            frame: FrameBuffer::new(),
+            display,
+            keypad: K::default(),
+            audio: A::default(),
+            rng: R::default(),
             delay_timer: 0,
             sound_timer: 0,
+            rpl: [0; XO_RPL_FLAGS],
+            platform: Platform::Chip8,
+            quirks,
+            render: true,
+            drawn: false,
+            exited: false,
+            speed: DEFAULT_SPEED,
+            speed_remainder: 0,
+            waiting_key: false,
+            #[cfg(feature = "std")]
+            state_path: None,
+            #[cfg(feature = "std")]
+            slot: 1,
+            tracer: None,
+            #[cfg(feature = "std")]
             debug: false,
         }
     }
 
-    pub fn debug(mut self) -> Self {
-        self.debug = true;
+    /// Sends every executed instruction to tracer
+    pub fn tracer<T: Tracer + 'static>(mut self, tracer: T) -> Self {
+        self.tracer = Some(Box::new(tracer));
         self
     }
 
+    /// Stops sending frames to the display
     pub fn no_display(mut self) -> Self {
-        self.display.should_update(false);
+        self.render = false;
+        self
+    }
+
+    /// Sets the instruction set of the interpreter
+    /// This must be called before loading a ROM, as XO-CHIP replaces the
+    /// memory with a 64 KiB one
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = platform;
+        if platform == Platform::XoChip {
+            self.mem = Memory::with_size(platform.memory_size());
+        }
+        self
+    }
+
+    /// Seeds the random number generator, so runs are reproducible
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.rng.seed(seed);
         self
     }
 
-    pub fn run(&mut self) {
-        // todo remove max
-        let max = 5000;
-        let mut i = 0;
-        while self.display.is_open() {
-            self.cycle();
-            i += 1;
-            if i == max {
+    /// If the program exited with 0x00FD
+    pub fn exited(&self) -> bool {
+        self.exited
+    }
+
+    /// Current value of the delay timer
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    /// Current value of the sound timer
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// If the CPU is blocked by 0xFX0A until a key is pressed and released
+    pub fn waiting_key(&self) -> bool {
+        self.waiting_key
+    }
+
+    /// If the SUPER-CHIP instructions are available
+    fn schip(&self) -> bool {
+        self.platform != Platform::Chip8
+    }
+
+    /// If the XO-CHIP instructions are available
+    fn xochip(&self) -> bool {
+        self.platform == Platform::XoChip
+    }
+
+    /// Number of RPL user flags available on the platform
+    fn rpl_flags(&self) -> usize {
+        if self.xochip() {
+            XO_RPL_FLAGS
+        } else {
+            RPL_FLAGS
+        }
+    }
+
+    /// Skips the next instruction, which is 4 bytes long if it's an XO-CHIP
+    /// 0xF000 NNNN
+    fn skip_instruction(&mut self) {
+        let next = self.mem.get_slice(self.cpu.pc.wrapping_add(2), 2);
+        if self.xochip() && next == Ok(&[0xF0, 0x00]) {
+            self.cpu.next_instruction();
+        }
+        self.cpu.skip_instruction();
+    }
+
+    /// Sets how many instructions are executed per second
+    pub fn speed(mut self, speed: u32) -> Self {
+        self.speed = speed.max(1);
+        self
+    }
+
+    /// Emulates one 60 Hz frame
+    ///
+    /// Executes the instructions that fit in a frame at the current speed,
+    /// sounds the buzzer while the sound timer is active, ticks the timers
+    /// once and then updates the display
+    /// An error stops the frame before its end
+    pub fn frame(&mut self) -> Result<(), Chip8Error> {
+        let cycles = self.frame_cycles();
+        for _ in 0..cycles {
+            if !self.can_cycle() {
                 break;
             }
+            self.cycle()?;
+        }
+        self.end_frame();
+        Ok(())
+    }
+
+    /// If another instruction can run in the current frame, which stops
+    /// once the program exits or, with the display wait quirk, draws
+    pub fn can_cycle(&self) -> bool {
+        !(self.exited || self.quirks.display_wait && self.drawn)
+    }
+
+    /// Starts a frame, returning how many instructions it should execute
+    pub fn frame_cycles(&mut self) -> u32 {
+        let mut cycles = self.speed / TIMER_HZ;
+        self.speed_remainder += self.speed % TIMER_HZ;
+        if self.speed_remainder >= TIMER_HZ {
+            self.speed_remainder -= TIMER_HZ;
+            cycles += 1;
+        }
+
+        self.drawn = false;
+        cycles
+    }
+
+    /// Ends a frame, sounding the buzzer, ticking the timers and updating
+    /// the display
+    pub fn end_frame(&mut self) {
+        self.audio.beep(self.sound_timer > 0);
+        self.audio.frame();
+
+        self.tick_timers();
+        self.present();
+    }
+
+    /// Sends the frame to the display unless it's off
+    fn present(&mut self) {
+        if self.render {
+            self.display.status(&Status {
+                pc: self.cpu.pc,
+                i: self.cpu.i,
+                v: self.cpu.v,
+                delay_timer: self.delay_timer,
+                sound_timer: self.sound_timer,
+            });
+            self.display.render(&self.frame);
+        }
+    }
+
+    /// Decrements the delay and sound timers
+    pub fn tick_timers(&mut self) {
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
         }
     }
 
-    /// Loads a ROM provided by data
-    pub fn load_rom(&mut self, data: &Vec<u8>) {
-        for i in 0..data.len() {
-            self.mem.write_byte(self.mem.offset + i as u16, data[i]);
+    /// Saves the whole machine state in a versioned binary format
+    ///
+    /// The quirks, speed, keypad and audio are configuration of the host, so
+    /// they are not part of the state
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        state.u8(self.platform as u8);
+        state.u16(self.cpu.pc);
+        state.u16(self.cpu.i);
+        state.bytes(&self.cpu.v);
+
+        let addresses = self.stack.addresses();
+        state.u8(addresses.len() as u8);
+        for addr in addresses {
+            state.u16(*addr);
         }
+
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.bytes(&self.rpl);
+        state.bool(self.drawn);
+        state.bool(self.exited);
+        state.bool(self.waiting_key);
+        state.u32(self.speed_remainder);
+        state.u64(self.rng.state());
+
+        state.u32(self.mem.size() as u32);
+        state.bytes(&self.mem.mem);
+
+        let (width, height) = self.frame.size();
+        state.u16(width);
+        state.u16(height);
+        state.u8(self.frame.planes());
+        for row in self.frame.rows() {
+            state.bytes(row);
+        }
+
+        state.finish()
+    }
+
+    /// Restores a machine state saved by save_state
+    /// Nothing is changed if the state can't be loaded
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data)?;
+
+        let platform = match state.u8()? {
+            0 => Platform::Chip8,
+            1 => Platform::SuperChip,
+            2 => Platform::XoChip,
+            _ => return Err(StateError::Invalid("platform")),
+        };
+        let mut cpu = Cpu::new();
+        cpu.pc = state.u16()?;
+        cpu.i = state.u16()?;
+        cpu.v.copy_from_slice(state.bytes(16)?);
+
+        let depth = state.u8()? as usize;
+        if depth > STACK_SIZE {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let mut stack = Stack::new();
+        for _ in 0..depth {
+            stack
+                .push(state.u16()?)
+                .map_err(|_| StateError::Invalid("stack pointer"))?;
+        }
+
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let mut rpl = [0; XO_RPL_FLAGS];
+        rpl.copy_from_slice(state.bytes(XO_RPL_FLAGS)?);
+        let drawn = state.bool()?;
+        let exited = state.bool()?;
+        let waiting_key = state.bool()?;
+        let speed_remainder = state.u32()?;
+        let rng = state.u64()?;
+        if speed_remainder >= TIMER_HZ {
+            return Err(StateError::Invalid("speed remainder"));
+        }
+
+        // The memory is the one of the platform, the ROM can't address more
+        let size = state.u32()? as usize;
+        if size != platform.memory_size() {
+            return Err(StateError::Invalid("memory size"));
+        }
+        let mut mem = Memory::with_size(size);
+        mem.mem.copy_from_slice(state.bytes(size)?);
+
+        let width = state.u16()? as usize;
+        let height = state.u16()? as usize;
+        let hires = match (width, height) {
+            (WIDTH, HEIGHT) => false,
+            (HIRES_WIDTH, HIRES_HEIGHT) => true,
+            _ => return Err(StateError::Invalid("display size")),
+        };
+        let planes = state.u8()?;
+        let pixels = state.bytes(width * height)?;
+        state.finish()?;
+
+        self.platform = platform;
+        self.cpu = cpu;
+        self.stack = stack;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.rpl = rpl;
+        self.drawn = drawn;
+        self.exited = exited;
+        self.waiting_key = waiting_key;
+        self.speed_remainder = speed_remainder;
+        self.rng.set_state(rng);
+        self.mem = mem;
+
+        self.frame.set_hires(hires);
+        self.frame.set_planes(planes);
+        for (i, pixel) in pixels.iter().enumerate() {
+            let (x, y) = (i % width, i / width);
+            self.frame.set_pixel(x as u16, y as u16, *pixel);
+        }
+
+        Ok(())
+    }
+
+    /// Loads a ROM provided by data, failing if it doesn't fit in memory
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let max = self.mem.size() - self.mem.offset as usize;
+        if data.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: data.len(),
+                max,
+            });
+        }
+        self.mem
+            .get_slice_mut(self.mem.offset, data.len())?
+            .copy_from_slice(data);
+        Ok(())
     }
 
     /// Sets the display on or off
-    #[allow(dead_code)]
     pub fn set_display(&mut self, on: bool) {
-        self.display.should_update(on);
+        self.render = on;
     }
 
     /// Runs an instruction for each cycle
-    pub fn cycle(&mut self) {
-        let l = self.mem.read_byte(self.cpu.pc) as u16;
-        let h = self.mem.read_byte(self.cpu.pc + 1) as u16;
+    /// On error nothing is changed, and PC stays on the faulting opcode
+    pub fn cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        let opcode = self.opcode()?;
+        let instruction = match decode(opcode) {
+            Ok(instruction) if self.supports(instruction) => instruction,
+            _ => {
+                #[cfg(feature = "std")]
+                if self.debug {
+                    eprintln!("{} at {:#X}", DecodeError(opcode), self.cpu.pc);
+                }
+                self.cpu.next_instruction();
+                return Ok(StepOutcome::Skipped(opcode));
+            }
+        };
+
+        let (pc, before) = (self.cpu.pc, self.cpu.v);
+        self.execute(instruction)?;
+        // 0xFX0A is traced once, on the cycle a key completes it
+        if let (Some(tracer), false) = (&mut self.tracer, self.waiting_key) {
+            tracer.trace(&Step {
+                pc,
+                opcode,
+                instruction,
+                before,
+                after: self.cpu.v,
+                i: self.cpu.i,
+            });
+        }
+
+        Ok(if self.exited {
+            StepOutcome::Exited
+        } else if self.waiting_key {
+            StepOutcome::WaitingKey
+        } else {
+            StepOutcome::Executed(instruction)
+        })
+    }
+
+    /// The opcode at PC
+    pub fn opcode(&self) -> Result<u16, Chip8Error> {
+        self.fetch(self.cpu.pc)
+    }
+
+    /// Reads the opcode at address
+    fn fetch(&self, address: u16) -> Result<u16, Chip8Error> {
+        let bytes = self.mem.get_slice(address, 2)?;
 
         // An instruction is based on two u8 (one u16)
-        let opcode = Opcode::new((l << 8) | h);
-
-        // Match the first nibble
-        match opcode[Nibble::A] {
-            0x0 => {
-                match opcode.get(Nibble::CD) {
-                    0xE0 => {
-                        // 0x00E0 -> Clear display
-                        if self.debug {
-                            println!("{:#X}\tCLS", self.cpu.pc);
-                        }
-                        self.display.clear();
-                    }
-                    0xEE => {
-                        // 0x00EE -> Return from subroutine
-                        if self.debug {
-                            println!("{:#X}\tRET", self.cpu.pc);
-                        }
-                        self.cpu.pc = self.stack.pop();
-                    }
-                    _ => match opcode.get(Nibble::ABCD) {
-                        0x0000 => {
-                            if self.debug {
-                                println!("{:#X}:\tNOP", self.cpu.pc);
-                            }
-                        }
-                        _ => {
-                            if self.debug {
-                                eprintln!(
-                                    "Unknown opcode for 0x0 at {} -> {}",
-                                    self.cpu.pc, opcode
-                                )
-                            }
-                        }
-                    },
-                }
+        Ok((bytes[0] as u16) << 8 | bytes[1] as u16)
+    }
+
+    /// If the instruction is part of the instruction set of the platform
+    pub fn supports(&self, instruction: Instruction) -> bool {
+        use Instruction::*;
+
+        match instruction {
+            Scd(_) | Scr | Scl | Exit | Low | High | LdHfVx(_) | LdRVx(_)
+            | LdVxR(_) => self.schip(),
+            Scu(_) | LdRangeIVx(..) | LdRangeVxI(..) | LdILong | Plane(_)
+            | Audio | Pitch(_) => self.xochip(),
+            _ => true,
+        }
+    }
+
+    /// Executes an instruction and moves the program counter past it
+    /// On error nothing is changed
+    pub fn execute(
+        &mut self,
+        instruction: Instruction,
+    ) -> Result<(), Chip8Error> {
+        use Instruction::*;
+
+        match instruction {
+            Nop | Sys(_) => self.cpu.next_instruction(),
+            Scd(n) => {
+                self.frame.scroll_down(n as u16);
                 self.cpu.next_instruction();
             }
-            0x1 => {
-                // 0x1NNN -> Jump to address NNN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tJP\t{:#X}",
-                        self.cpu.pc,
-                        opcode.get(Nibble::BCD)
-                    );
-                }
-                self.cpu.pc = opcode.get(Nibble::BCD);
+            Scu(n) => {
+                self.frame.scroll_up(n as u16);
+                self.cpu.next_instruction();
             }
-            0x2 => {
-                // 0x2NNN -> Call subroutine at NNN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tCALL\t{:#X}",
-                        self.cpu.pc,
-                        opcode.get(Nibble::BCD)
-                    );
-                }
+            Cls => {
+                self.frame.clear();
                 self.cpu.next_instruction();
-                self.stack.push(self.cpu.pc);
-                self.cpu.pc = opcode.get(Nibble::BCD);
             }
-            0x3 => {
-                // 0x3XNN -> Skips the next instruction if VX equals NN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tSE\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode.get(Nibble::CD)
-                    );
-                }
-                if self.cpu.v[opcode[Nibble::B] as usize] as u16
-                    == opcode.get(Nibble::CD)
-                {
-                    self.cpu.skip_instruction();
-                } else {
-                    self.cpu.next_instruction();
-                }
+            Ret => self.cpu.pc = self.stack.pop()?,
+            Scr => {
+                self.frame.scroll_right(4);
+                self.cpu.next_instruction();
             }
-            0x4 => {
-                // 0x4XNN -> Skips the next instruction if VX doesn't equal NN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tSNE\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode.get(Nibble::CD)
-                    );
-                }
-                if self.cpu.v[opcode[Nibble::B] as usize] as u16
-                    != opcode.get(Nibble::CD)
-                {
-                    self.cpu.skip_instruction();
-                } else {
-                    self.cpu.next_instruction();
-                }
+            Scl => {
+                self.frame.scroll_left(4);
+                self.cpu.next_instruction();
             }
-            0x5 => {
-                // 0x5XY0 -> Skips the next instruction if VX == VY
-                // Here, 0x000X is ignored
-                if self.debug {
-                    println!(
-                        "{:#X}:\tSE\tV{},\tV{}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode[Nibble::C]
-                    );
-                }
-                if self.cpu.v[opcode[Nibble::B] as usize]
-                    == self.cpu.v[opcode[Nibble::C] as usize]
+            // The program counter stays at the exit instruction
+            Exit => self.exited = true,
+            Low => {
+                self.frame.set_hires(false);
+                self.cpu.next_instruction();
+            }
+            High => {
+                self.frame.set_hires(true);
+                self.cpu.next_instruction();
+            }
+            Jp(addr) => self.cpu.pc = addr,
+            Call(addr) => {
+                self.stack.push(self.cpu.pc.wrapping_add(2))?;
+                self.cpu.pc = addr;
+            }
+            SeVxByte(x, nn) => self.skip_if(self.cpu.v[x as usize] == nn),
+            SneVxByte(x, nn) => self.skip_if(self.cpu.v[x as usize] != nn),
+            SeVxVy(x, y) => {
+                self.skip_if(self.cpu.v[x as usize] == self.cpu.v[y as usize])
+            }
+            SneVxVy(x, y) => {
+                self.skip_if(self.cpu.v[x as usize] != self.cpu.v[y as usize])
+            }
+            LdRangeIVx(x, y) => {
+                // I is not changed, and VX can come after VY
+                let n = x.abs_diff(y) as usize + 1;
+                let bytes = self.mem.get_slice_mut(self.cpu.i, n)?;
+                for (byte, v) in
+                    bytes.iter_mut().zip(Self::register_range(x, y))
                 {
-                    self.cpu.skip_instruction();
-                } else {
-                    self.cpu.next_instruction();
+                    *byte = self.cpu.v[v as usize];
                 }
+                self.cpu.next_instruction();
             }
-            0x6 => {
-                // 0x6XNN -> VX = NN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tLD\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode.get(Nibble::CD)
-                    );
+            LdRangeVxI(x, y) => {
+                // I is not changed, and VX can come after VY
+                let n = x.abs_diff(y) as usize + 1;
+                let bytes = self.mem.get_slice(self.cpu.i, n)?;
+                for (byte, v) in bytes.iter().zip(Self::register_range(x, y)) {
+                    self.cpu.v[v as usize] = *byte;
                 }
-                self.cpu.write_register(
-                    opcode[Nibble::B],
-                    opcode.get(Nibble::CD) as u8,
-                );
                 self.cpu.next_instruction();
             }
-            0x7 => {
-                // 0x7XNN -> VX += NN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tADD\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode.get(Nibble::CD)
-                    );
-                }
-                self.cpu.write_register(
-                    opcode[Nibble::B],
-                    (self.cpu.v[opcode[Nibble::B] as usize] as u16
-                        + opcode.get(Nibble::CD)) as u8,
+            LdVxByte(x, nn) => {
+                self.cpu.write_register(x, nn);
+                self.cpu.next_instruction();
+            }
+            AddVxByte(x, nn) => {
+                let sum = self.cpu.v[x as usize].wrapping_add(nn);
+                self.cpu.write_register(x, sum);
+                self.cpu.next_instruction();
+            }
+            LdVxVy(x, y)
+            | Or(x, y)
+            | And(x, y)
+            | Xor(x, y)
+            | AddVxVy(x, y)
+            | Sub(x, y)
+            | Shr(x, y)
+            | Subn(x, y)
+            | Shl(x, y) => {
+                self.arithmetic(instruction, x, y);
+                self.cpu.next_instruction();
+            }
+            LdI(addr) => {
+                self.cpu.i = addr;
+                self.cpu.next_instruction();
+            }
+            JpV0(addr) => {
+                // With the jump quirk this is 0xBXNN, a jump to VX + XNN
+                let v = if self.quirks.jump_vx { addr >> 8 } else { 0 };
+                self.cpu.pc = self.cpu.v[v as usize] as u16 + addr;
+            }
+            Rnd(x, nn) => {
+                let random = self.rng.byte();
+                self.cpu.write_register(x, random & nn);
+                self.cpu.next_instruction();
+            }
+            Drw(x, y, n) => {
+                self.draw(x, y, n)?;
+                self.cpu.next_instruction();
+            }
+            Skp(x) => {
+                self.skip_if(self.keypad.is_pressed(self.cpu.v[x as usize]))
+            }
+            Sknp(x) => {
+                self.skip_if(!self.keypad.is_pressed(self.cpu.v[x as usize]))
+            }
+            LdILong => {
+                self.cpu.i = self.fetch(self.cpu.pc.wrapping_add(2))?;
+                self.cpu.skip_instruction();
+            }
+            Plane(n) => {
+                self.frame.set_planes(n);
+                self.cpu.next_instruction();
+            }
+            Audio => {
+                let mut pattern = [0; PATTERN_SIZE];
+                pattern.copy_from_slice(
+                    self.mem.get_slice(self.cpu.i, PATTERN_SIZE)?,
                 );
+                self.audio.pattern(&pattern);
                 self.cpu.next_instruction();
             }
-            0x8 => {
-                let vx = opcode[Nibble::B];
-                let vy = opcode[Nibble::C];
-                let vx_value = self.cpu.v[vx as usize];
-                let vy_value = self.cpu.v[vy as usize];
-                match opcode[Nibble::D] {
-                    // [0, 1, 2, 3, 4, 5, 6, 7, E]
-                    0x0 => {
-                        // 0x8XY0 -> VX = VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tLD\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vy_value);
-                    }
-                    0x1 => {
-                        // 0x8XY1 -> VX = VX | VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tOR\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vx_value | vy_value);
-                    }
-                    0x2 => {
-                        // 0x8XY2 -> VX = VX & VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tAND\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vx_value & vy_value);
-                    }
-                    0x3 => {
-                        // 0x8XY3 -> VX = VX ^ VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tXOR\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vx_value ^ vy_value);
-                    }
-                    0x4 => {
-                        // 0x8XY4 -> VX += VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tADD\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        let sum: u16 = vx_value as u16 + vy_value as u16;
-                        self.cpu.write_register(vx, sum as u8);
-                        self.cpu.v[0xF] = (sum > 0xFF) as u8;
-                    }
-                    0x5 => {
-                        // 0x8XY5 -> VX = VX - VY
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tSUB\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        let sub: i8 = vx_value as i8 - vy_value as i8;
-                        self.cpu.write_register(vx, sub as u8);
-                        self.cpu
-                            .write_register(0xF, (vx_value < vy_value) as u8);
-                    }
-                    0x6 => {
-                        // 0x8XY6 -> VX >>= 1
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tSHR\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vx_value >> 1);
-                        self.cpu.write_register(0xF, vx_value & 0x1);
-                    }
-                    0x7 => {
-                        // 0x8XY7 -> VX = VY - VX
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tSUBN\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        let sub: i8 = vy_value as i8 - vx_value as i8;
-                        self.cpu.write_register(vx, sub as u8);
-                        self.cpu
-                            .write_register(0xF, (vy_value < vx_value) as u8);
-                    }
-                    0xE => {
-                        // 0x8XYE -> VX <<= 1
-                        if self.debug {
-                            println!(
-                                "{:#X}:\tSHL\tV{},\tV{}",
-                                self.cpu.pc, vx, vy
-                            );
-                        }
-                        self.cpu.write_register(vx, vx_value << 1);
-                        self.cpu.write_register(0xF, vx_value >> 7);
-                    }
-                    _ => {
-                        if self.debug {
-                            eprintln!(
-                                "Unknown opcode for 0x8 at {} -> {}",
-                                self.cpu.pc, opcode
-                            );
-                        }
-                    }
-                }
+            LdVxDt(x) => {
+                self.cpu.write_register(x, self.delay_timer);
                 self.cpu.next_instruction();
             }
-            0x9 => {
-                // 0x9XY0 -> Skip next instruction if VX != VY
-                // Here, 0x000X is ignored
-                if self.debug {
-                    println!(
-                        "{:#X}:\tSNE\tV{},\tV{}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode[Nibble::C]
-                    );
+            LdVxK(x) => {
+                // The program counter is kept here until a key is pressed
+                // and released, so the host keeps running
+                if !self.waiting_key {
+                    // Only count keys released after the wait started
+                    self.keypad.take_released();
+                    self.waiting_key = true;
                 }
-                if self.cpu.v[opcode[Nibble::B] as usize]
-                    != self.cpu.v[opcode[Nibble::C] as usize]
-                {
-                    self.cpu.skip_instruction();
-                } else {
+                if let Some(key) = self.keypad.take_released() {
+                    self.cpu.write_register(x, key);
+                    self.waiting_key = false;
                     self.cpu.next_instruction();
                 }
             }
-            0xA => {
-                // 0xANNN -> Set I to NNN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tLD\tI,\t{:#X}",
-                        self.cpu.pc,
-                        opcode.get(Nibble::BCD)
-                    );
-                }
-                self.cpu.i = opcode.get(Nibble::BCD);
+            LdDtVx(x) => {
+                self.delay_timer = self.cpu.v[x as usize];
                 self.cpu.next_instruction();
             }
-            0xB => {
-                // 0xBNNN -> Jump to location V0 + NNN
-                if self.debug {
-                    println!(
-                        "{:#X}:\tJP\tV0,\t{:#X}",
-                        self.cpu.pc,
-                        opcode.get(Nibble::BCD)
-                    );
-                }
-                self.cpu.pc = self.cpu.v[0x0] as u16 + opcode[Nibble::BCD];
+            LdStVx(x) => {
+                self.sound_timer = self.cpu.v[x as usize];
+                self.cpu.next_instruction();
             }
-            0xC => {
-                // 0xCXKK -> VX = random() & KK
-                if self.debug {
-                    println!(
-                        "{:#X}:\tRND\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode.get(Nibble::CD)
-                    );
+            AddIVx(x) => {
+                // VF is set to 1 if I + VX > 0xFFF, except on XO-CHIP where
+                // I can address the whole 64 KiB
+                self.cpu.i =
+                    self.cpu.i.wrapping_add(self.cpu.v[x as usize] as u16);
+                if !self.xochip() {
+                    self.cpu.write_register(0xF, (self.cpu.i > 0xFFF) as u8);
                 }
-                self.cpu.v[Nibble::B as usize] = self.rng.gen::<u8>();
                 self.cpu.next_instruction();
             }
-            0xD => {
-                // 0xDXYN -> Draw sprite at (VX, VY) with width 8 and height N
-                if self.debug {
-                    println!(
-                        "{:#X}:\tDRW\tV{},\tV{},\t{:#X}",
-                        self.cpu.pc,
-                        opcode[Nibble::B],
-                        opcode[Nibble::C],
-                        opcode[Nibble::D],
-                    );
-                }
-                self.cpu.v[0xF] = self.display.display(
-                    opcode[Nibble::B],
-                    opcode[Nibble::C],
-                    opcode[Nibble::D],
-                    self.mem.get_slice(self.cpu.i, opcode[Nibble::D]),
-                ) as u8;
-                self.display.update();
+            LdFVx(x) => {
+                self.cpu.i = self.cpu.v[x as usize] as u16 * 0x5;
                 self.cpu.next_instruction();
             }
-            // todo 0xE
-            0xF => {
-                // [07, 0A, 15, 18, 1E, 29, 33, 55, 65]
-                let vx = opcode[Nibble::B];
-                match opcode.get(Nibble::CD) {
-                    0x07 => {
-                        // Set Vx = delay timer value
-                        if self.debug {
-                            println!("{:#X}:\tLD\tV{},\tDT", self.cpu.pc, vx);
-                        }
-                        self.cpu.write_register(vx, self.delay_timer);
-                    }
-                    0x0A => {
-                        // 0xFX0A -> Wait for key press, then store key in VX
-                        if self.debug {
-                            println!("{:#X}:\tLD\tV{},\tK", self.cpu.pc, vx);
-                            // todo wait for key press and store key into vx
-                            // todo this needs to be locked here
-                        }
-                    }
-                    0x15 => {
-                        // 0xFX15 -> Set delay timer = Vx
-                        if self.debug {
-                            println!("{:#X}:\tLD\tDT,\tV{}", self.cpu.pc, vx);
-                        }
-                        self.delay_timer = self.cpu.v[vx as usize];
-                    }
-                    0x18 => {
-                        // 0xFX18 -> Set sound timer = VX
-                        if self.debug {
-                            println!("{:#X}:\tLD\tST,\tV{}", self.cpu.pc, vx);
-                        }
-                    }
-                    0x1E => {
-                        // 0xFX1E -> I = I + Vx
-                        // VF is set to 1 if I + VX > 0xFFF
-                        if self.debug {
-                            println!("{:#X}:\tADD\tI,\tV{}", self.cpu.pc, vx);
-                        }
-                        self.cpu.i += self.cpu.v[vx as usize] as u16;
-                        self.cpu.write_register(
-                            0xF,
-                            (self.cpu.i + vx > 0xFFF) as u8,
-                        );
-                    }
-                    0x29 => {
-                        // 0xFX29 -> Set I to sprite location for the character in VX
-                        if self.debug {
-                            println!("{:#X}:\tLD\tF\tV{}", self.cpu.pc, vx);
-                        }
-                        self.cpu.i = self.cpu.v[vx as usize] as u16 * 0x5;
-                    }
-                    0x33 => {
-                        // 0xFX33 -> Store BCD repr of VX in memory locations I..I + 2
-                        if self.debug {
-                            println!("{:#X}:\tLD\tB\tV{}", self.cpu.pc, vx);
-                        }
-                        let vx_value = self.cpu.v[vx as usize];
-                        self.mem.write_byte(self.cpu.i, vx_value / 100);
-                        self.mem
-                            .write_byte(self.cpu.i + 1, (vx_value / 10) % 10);
-                        self.mem.write_byte(self.cpu.i + 2, vx_value % 10);
-                    }
-                    0x55 => {
-                        // 0xFX55 -> Store V0 through VX starting at memory location I
-                        if self.debug {
-                            println!("{:#X}:\tLD\t[I]\tV{}", self.cpu.pc, vx);
-                        }
-                        for i in 0..=vx {
-                            self.mem.write_byte(
-                                self.cpu.i + i,
-                                self.cpu.v[i as usize],
-                            );
-                        }
-                        self.cpu.i += vx + 1;
-                    }
-                    0x65 => {
-                        // 0xFX65 -> Store values at memory location I from V0 through VX
-                        if self.debug {
-                            println!("{:#X}:\tLD\tV{}\t[I]", self.cpu.pc, vx);
-                        }
-                        for i in 0..=vx {
-                            self.cpu.v[i as usize] =
-                                self.mem.read_byte(self.cpu.i + i);
-                        }
-                        self.cpu.i += vx + 1;
-                    }
-                    _ => {
-                        if self.debug {
-                            eprintln!(
-                                "Unknown opcode for 0xF at {} -> {}",
-                                self.cpu.pc, opcode
-                            )
-                        }
-                    }
-                }
+            LdHfVx(x) => {
+                self.cpu.i = BIG_SPRITES_OFFSET
+                    + (self.cpu.v[x as usize] & 0xF) as u16 * 10;
                 self.cpu.next_instruction();
             }
-            _ => {
-                if self.debug {
-                    eprintln!(
-                        "Unknown opcode at {:#X} -> {}",
-                        self.cpu.pc, opcode
-                    );
-                }
+            LdBVx(x) => {
+                let vx_value = self.cpu.v[x as usize];
+                self.mem.get_slice_mut(self.cpu.i, 3)?.copy_from_slice(&[
+                    vx_value / 100,
+                    (vx_value / 10) % 10,
+                    vx_value % 10,
+                ]);
+                self.cpu.next_instruction();
+            }
+            Pitch(x) => {
+                self.audio.pitch(self.cpu.v[x as usize]);
+                self.cpu.next_instruction();
+            }
+            LdIVx(x) => {
+                let n = x as usize + 1;
+                self.mem
+                    .get_slice_mut(self.cpu.i, n)?
+                    .copy_from_slice(&self.cpu.v[..n]);
+                self.increment_index(x as u16);
+                self.cpu.next_instruction();
+            }
+            LdVxI(x) => {
+                let n = x as usize + 1;
+                let bytes = self.mem.get_slice(self.cpu.i, n)?;
+                self.cpu.v[..n].copy_from_slice(bytes);
+                self.increment_index(x as u16);
+                self.cpu.next_instruction();
+            }
+            LdRVx(x) => {
+                let n = (x as usize + 1).min(self.rpl_flags());
+                self.rpl[..n].copy_from_slice(&self.cpu.v[..n]);
+                self.cpu.next_instruction();
+            }
+            LdVxR(x) => {
+                let n = (x as usize + 1).min(self.rpl_flags());
+                self.cpu.v[..n].copy_from_slice(&self.rpl[..n]);
                 self.cpu.next_instruction();
             }
         }
+        Ok(())
+    }
 
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+    /// Executes the 0x8XYN register operations
+    fn arithmetic(&mut self, instruction: Instruction, x: Reg, y: Reg) {
+        use Instruction::*;
+
+        let vx_value = self.cpu.v[x as usize];
+        let vy_value = self.cpu.v[y as usize];
+        // Shifts use VY as the source with the shift quirk
+        let shifted = if self.quirks.shift_vy {
+            vy_value
+        } else {
+            vx_value
+        };
+
+        // The result is written before the flag, so VF as VX keeps the flag
+        let (result, flag) = match instruction {
+            LdVxVy(..) => (vy_value, None),
+            Or(..) => (vx_value | vy_value, self.logic_flag()),
+            And(..) => (vx_value & vy_value, self.logic_flag()),
+            Xor(..) => (vx_value ^ vy_value, self.logic_flag()),
+            AddVxVy(..) => {
+                let (sum, carry) = vx_value.overflowing_add(vy_value);
+                (sum, Some(carry as u8))
+            }
+            Sub(..) => {
+                let (sub, borrow) = vx_value.overflowing_sub(vy_value);
+                (sub, Some(!borrow as u8))
+            }
+            Subn(..) => {
+                let (sub, borrow) = vy_value.overflowing_sub(vx_value);
+                (sub, Some(!borrow as u8))
+            }
+            Shr(..) => (shifted >> 1, Some(shifted & 0x1)),
+            Shl(..) => (shifted << 1, Some(shifted >> 7)),
+            _ => unreachable!("{} is not a register operation", instruction),
+        };
+
+        self.cpu.write_register(x, result);
+        if let Some(flag) = flag {
+            self.cpu.write_register(0xF, flag);
         }
+    }
 
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+    /// VF after the 0x8XY1, 0x8XY2 and 0x8XY3 logic operations
+    fn logic_flag(&self) -> Option<u8> {
+        if self.quirks.vf_reset {
+            Some(0)
+        } else {
+            None
         }
     }
 
-    #[allow(dead_code)]
+    /// Draws the sprite at I on (VX, VY) with height N, setting VF on
+    /// collision
+    fn draw(&mut self, x: Reg, y: Reg, n: u8) -> Result<(), Chip8Error> {
+        // 0xDXY0 draws a 16x16 sprite on SUPER-CHIP
+        let (width, height) = match n {
+            0x0 if self.schip() => (16, 16),
+            n => (8, n as u16),
+        };
+        // Each selected XO-CHIP plane has its own sprite
+        let planes = self.frame.planes().count_ones() as usize;
+        let sprite = self
+            .mem
+            .get_slice(self.cpu.i, (width / 8 * height) as usize * planes)?;
+        let flipped = self.frame.draw(
+            self.cpu.v[x as usize] as u16,
+            self.cpu.v[y as usize] as u16,
+            width,
+            height,
+            sprite,
+            self.quirks.wrap,
+        );
+        self.cpu.write_register(0xF, flipped as u8);
+        self.drawn = true;
+        Ok(())
+    }
+
+    /// Skips the next instruction if condition is true, otherwise goes to it
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.skip_instruction();
+        } else {
+            self.cpu.next_instruction();
+        }
+    }
+
+    /// Increments I after 0xFX55 or 0xFX65 according to the quirks
+    fn increment_index(&mut self, vx: u16) {
+        match self.quirks.index_increment {
+            IndexIncrement::Unchanged => {}
+            IndexIncrement::X => self.cpu.i = self.cpu.i.wrapping_add(vx),
+            IndexIncrement::XPlusOne => {
+                self.cpu.i = self.cpu.i.wrapping_add(vx + 1)
+            }
+        }
+    }
+
+    /// Registers from VX to VY, in descending order if VX comes after VY
+    fn register_range(vx: Reg, vy: Reg) -> impl Iterator<Item = Reg> {
+        let count = vx.abs_diff(vy);
+        (0..=count).map(move |i| if vx <= vy { vx + i } else { vx - i })
+    }
+}
+
+/// Frontend features that need the standard library: files, printing and
+/// wall-clock time
+#[cfg(feature = "std")]
+impl<D, K, A, R> Chip8<D, K, A, R>
+where
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
+{
+    /// Prints the executed instructions and the invalid opcodes
+    pub fn debug(mut self) -> Self {
+        self.debug = true;
+        self.trace(io::stdout())
+    }
+
+    /// Writes every executed instruction to out
+    pub fn trace<W: Write + 'static>(self, out: W) -> Self {
+        self.tracer(WriteTracer::new(out))
+    }
+
+    /// Enables the quick save hotkeys, saving each slot next to path
+    pub fn state_path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.state_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Runs the emulator until the display is closed or the program exits
+    ///
+    /// Frames are scheduled at TIMER_HZ based on wall-clock time, and each
+    /// one executes its share of instructions before ticking the timers
+    /// A snapshot is taken every frame, and while the rewind hotkey is held
+    /// frames go back through them instead
+    /// Stops at the first error, with the display showing the last frame
+    pub fn run(&mut self) -> Result<(), Chip8Error> {
+        let frame_time = Duration::from_secs(1) / TIMER_HZ;
+        let mut last = Instant::now();
+        let mut lag = Duration::from_secs(0);
+        let mut rewind = Rewind::default();
+        let mut frames: u64 = 0;
+        let mut rewinding = false;
+        while !self.exited && self.display.is_open() {
+            self.display.poll_keys(&mut self.keypad);
+            match self.display.poll_hotkey() {
+                Some(Hotkey::Rewind(on)) => rewinding = on,
+                Some(hotkey) => self.hotkey(hotkey),
+                None => {}
+            }
+
+            let now = Instant::now();
+            lag += now - last;
+            last = now;
+            if lag > MAX_LAG {
+                lag = MAX_LAG;
+            }
+
+            if lag < frame_time {
+                if !self.display.paced() {
+                    thread::sleep(frame_time - lag);
+                }
+                continue;
+            }
+
+            while lag >= frame_time {
+                if rewinding {
+                    let previous = frames.saturating_sub(1);
+                    if let Some(time) = rewind.restore(previous, self) {
+                        frames = time;
+                    }
+                    self.present();
+                } else {
+                    if let Err(e) = self.frame() {
+                        self.present();
+                        return Err(e);
+                    }
+                    frames += 1;
+                    rewind.record(frames, self);
+                }
+                lag -= frame_time;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a frontend hotkey
+    fn hotkey(&mut self, hotkey: Hotkey) {
+        let path = match &self.state_path {
+            Some(path) => path.with_extension(format!("state{}", self.slot)),
+            None => return,
+        };
+        let message = match hotkey {
+            // Rewinding is handled by run, which owns the snapshots
+            Hotkey::Rewind(_) => return,
+            Hotkey::Slot(slot) => {
+                self.slot = slot;
+                format!("Selected slot {}", slot)
+            }
+            Hotkey::Save => match fs::write(&path, self.save_state()) {
+                Ok(()) => format!("Saved state to {}", path.display()),
+                Err(e) => format!("Could not save {}: {}", path.display(), e),
+            },
+            Hotkey::Load => {
+                let loaded = fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|data| {
+                        self.load_state(&data).map_err(|e| e.to_string())
+                    });
+                match loaded {
+                    Ok(()) => format!("Loaded state from {}", path.display()),
+                    Err(e) => {
+                        format!("Could not load {}: {}", path.display(), e)
+                    }
+                }
+            }
+        };
+        // The renderer owns the screen, so it shows the result
+        self.display.message(&message);
+    }
+
     pub fn dump(&self) {
         self.mem.dump();
         self.cpu.dump();
@@ -1,3 +1,5 @@
+use crate::opcode::Reg;
+
 #[derive(Debug)]
 pub struct Cpu {
     /// index register
@@ -19,20 +21,20 @@ impl Cpu {
 
     /// Goes to the next instruction
     pub fn next_instruction(&mut self) {
-        self.pc += 2;
+        self.pc = self.pc.wrapping_add(2);
     }
 
     /// Skips one instruction and goes to the next one
     pub fn skip_instruction(&mut self) {
-        self.pc += 4;
+        self.pc = self.pc.wrapping_add(4);
     }
 
     /// Writes a value val to a register v
-    pub fn write_register(&mut self, v: u16, val: u8) {
+    pub fn write_register(&mut self, v: Reg, val: u8) {
         self.v[v as usize] = val;
     }
 
-    #[allow(dead_code)]
+    #[cfg(feature = "std")]
     pub fn dump(&self) {
         println!(
             "Cpu {{ i: {}, pc: {:#X}, v: {:?} }}",
@@ -40,3 +42,9 @@ impl Cpu {
         );
     }
 }
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::audio::Audio;
+use crate::chip8::Chip8;
+use crate::display::Renderer;
+use crate::error::Chip8Error;
+use crate::keypad::{Keypad, KEY_COUNT};
+use crate::opcode::{decode, Addr, Instruction, Reg};
+use crate::random::Random;
+use crate::rewind::Rewind;
+
+/// Commands understood by the debugger, printed by help
+const HELP: &str = "\
+s, step [n]          execute n instructions
+n, next              execute an instruction, running a CALL until it returns
+rs, reverse-step [n] go back n instructions
+c, continue          run until a breakpoint or watchpoint is hit
+b, break <addr>      stop when PC reaches addr
+d, delete <addr>     delete the breakpoint at addr
+w, watch <target>    stop when VX, I or the byte at addr changes
+unwatch <target>     delete the watchpoint on target
+i, info              list the breakpoints and watchpoints
+r, regs              print the registers and timers
+bt, stack            print the call stack
+x, mem <addr> [n]    print n bytes of memory starting at addr
+l, list [addr] [n]   disassemble n instructions starting at addr or PC
+screen               print the display
+press <key>          press a key of the keypad, from 0 to F
+release <key>        release a key of the keypad
+h, help              print this help
+q, quit              exit the debugger
+An empty line repeats the last command";
+
+/// Instructions continue runs before giving up on reaching a breakpoint,
+/// a few hours of most programs
+const RESUME_LIMIT: u64 = 10_000_000;
+
+/// A value watched for changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    /// A byte of memory
+    Memory(Addr),
+    /// A V register
+    Register(Reg),
+    /// The I register
+    Index,
+}
+
+impl FromStr for Watch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        if upper == "I" {
+            return Ok(Watch::Index);
+        }
+        if let Some(x) = upper.strip_prefix('V') {
+            if x.len() == 1 {
+                if let Ok(x) = Reg::from_str_radix(x, 16) {
+                    return Ok(Watch::Register(x));
+                }
+            }
+        }
+        number(s).map(Watch::Memory)
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Watch::Memory(addr) => write!(f, "[{:#05X}]", addr),
+            Watch::Register(x) => write!(f, "V{:X}", x),
+            Watch::Index => write!(f, "I"),
+        }
+    }
+}
+
+/// A command of the debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Executes n instructions
+    Step(u32),
+    /// Executes an instruction, running a CALL until it returns
+    Next,
+    /// Goes back n instructions
+    ReverseStep(u32),
+    /// Runs until a breakpoint or watchpoint is hit
+    Continue,
+    /// Stops when PC reaches an address
+    Break(Addr),
+    /// Deletes the breakpoint at an address
+    Delete(Addr),
+    /// Stops when a value changes
+    Watch(Watch),
+    /// Deletes the watchpoint on a value
+    Unwatch(Watch),
+    /// Lists the breakpoints and watchpoints
+    Info,
+    /// Prints the registers and timers
+    Registers,
+    /// Prints the call stack
+    Stack,
+    /// Prints n bytes of memory starting at an address
+    Memory(Addr, u16),
+    /// Disassembles n instructions starting at an address, or PC
+    List(Option<Addr>, u16),
+    /// Prints the display
+    Screen,
+    /// Presses a key of the keypad
+    Press(u8),
+    /// Releases a key of the keypad
+    Release(u8),
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| -> Result<&str, String> {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| format!("Missing argument for {}", name))
+        };
+        let key = |s: &str| match u8::from_str_radix(s, 16) {
+            Ok(key) if (key as usize) < KEY_COUNT => Ok(key),
+            _ => Err(format!("Invalid key {}", s)),
+        };
+
+        let command = match name {
+            "s" | "step" => match args.first() {
+                Some(n) => Command::Step(number(n)? as u32),
+                None => Command::Step(1),
+            },
+            "n" | "next" => Command::Next,
+            "rs" | "reverse-step" => match args.first() {
+                Some(n) => Command::ReverseStep(number(n)? as u32),
+                None => Command::ReverseStep(1),
+            },
+            "c" | "continue" => Command::Continue,
+            "b" | "break" => Command::Break(number(arg(0)?)?),
+            "d" | "delete" => Command::Delete(number(arg(0)?)?),
+            "w" | "watch" => Command::Watch(arg(0)?.parse()?),
+            "unwatch" => Command::Unwatch(arg(0)?.parse()?),
+            "i" | "info" => Command::Info,
+            "r" | "regs" => Command::Registers,
+            "bt" | "stack" => Command::Stack,
+            "x" | "mem" => Command::Memory(
+                number(arg(0)?)?,
+                args.get(1).map_or(Ok(16), |n| number(n))?,
+            ),
+            "l" | "list" => Command::List(
+                args.first().map(|addr| number(addr)).transpose()?,
+                args.get(1).map_or(Ok(8), |n| number(n))?,
+            ),
+            "screen" => Command::Screen,
+            "press" => Command::Press(key(arg(0)?)?),
+            "release" => Command::Release(key(arg(0)?)?),
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            _ => return Err(format!("Unknown command {}", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// Why the execution stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// The requested instructions were executed
+    Done,
+    /// PC reached a breakpoint
+    Breakpoint(Addr),
+    /// A watched value changed from the first value to the second
+    Watchpoint(Watch, u16, u16),
+    /// The CPU is blocked by 0xFX0A until a key is pressed and released
+    WaitingKey,
+    /// The program exited with 0x00FD
+    Exited,
+    /// The instruction at PC failed
+    Crashed(Chip8Error),
+    /// Nothing stopped the program after this many instructions
+    Limit(u64),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Stop::Done => Ok(()),
+            Stop::Breakpoint(addr) => write!(f, "Breakpoint at {:#05X}", addr),
+            Stop::Watchpoint(watch, old, new) => {
+                write!(f, "{} changed from {:#X} to {:#X}", watch, old, new)
+            }
+            Stop::WaitingKey => write!(f, "Waiting for a key"),
+            Stop::Exited => write!(f, "The program exited"),
+            Stop::Crashed(e) => write!(f, "Crashed: {}", e),
+            Stop::Limit(n) => write!(f, "Stopped after {} instructions", n),
+        }
+    }
+}
+
+/// An interactive debugger wrapping a Chip8
+pub struct Debugger<D, K, A, R>
+where
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
+{
+    /// The debugged emulator
+    pub chip8: Chip8<D, K, A, R>,
+    /// Addresses where execution stops
+    breakpoints: BTreeSet<Addr>,
+    /// Watched values along with their last value
+    watches: Vec<(Watch, u16)>,
+    /// Instructions left to execute in the current frame
+    frame_cycles: u32,
+    /// Instructions executed so far
+    time: u64,
+    /// Snapshots taken at the start of each frame
+    rewind: Rewind,
+    /// Keys pressed or released by commands, along with the time they were
+    inputs: Vec<(u64, u8, bool)>,
+    /// Instructions resume runs at most
+    limit: u64,
+}
+
+impl<D, K, A, R> Debugger<D, K, A, R>
+where
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
+{
+    pub fn new(chip8: Chip8<D, K, A, R>) -> Self {
+        Self {
+            chip8,
+            breakpoints: BTreeSet::new(),
+            watches: Vec::new(),
+            frame_cycles: 0,
+            time: 0,
+            rewind: Rewind::default(),
+            inputs: Vec::new(),
+            limit: RESUME_LIMIT,
+        }
+    }
+
+    /// Stops resuming after limit instructions, so infinite loops return
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Executes an instruction, taking a snapshot at the start of each
+    /// frame and ending the frame after the last of its instructions so the
+    /// timers keep running
+    fn cycle(&mut self) -> Result<(), Chip8Error> {
+        if self.frame_cycles == 0 {
+            self.rewind.record(self.time, &self.chip8);
+            self.frame_cycles = self.chip8.frame_cycles().max(1);
+        }
+
+        self.chip8.cycle()?;
+        self.time += 1;
+        self.frame_cycles -= 1;
+        // Like Chip8::frame, a draw with the display wait quirk ends it early
+        if self.frame_cycles == 0 || !self.chip8.can_cycle() {
+            self.frame_cycles = 0;
+            self.chip8.end_frame();
+        }
+        Ok(())
+    }
+
+    /// Executes a single instruction, checking the breakpoints and
+    /// watchpoints after it
+    pub fn step(&mut self) -> Stop {
+        if self.chip8.exited() {
+            return Stop::Exited;
+        }
+        if let Err(e) = self.cycle() {
+            return Stop::Crashed(e);
+        }
+
+        let mut stop = Stop::Done;
+        for i in 0..self.watches.len() {
+            let (watch, old) = self.watches[i];
+            let new = self.value(watch);
+            if new != old {
+                self.watches[i].1 = new;
+                if stop == Stop::Done {
+                    stop = Stop::Watchpoint(watch, old, new);
+                }
+            }
+        }
+
+        if stop != Stop::Done {
+            stop
+        } else if self.chip8.exited() {
+            Stop::Exited
+        } else if self.chip8.waiting_key() {
+            Stop::WaitingKey
+        } else if self.breakpoints.contains(&self.chip8.cpu.pc) {
+            Stop::Breakpoint(self.chip8.cpu.pc)
+        } else {
+            Stop::Done
+        }
+    }
+
+    /// Goes back n instructions, by restoring the last snapshot before them
+    /// and executing again the instructions and key presses since then
+    pub fn reverse_step(&mut self, n: u64) -> Result<(), String> {
+        let target = self.time.saturating_sub(n);
+        match self.rewind.oldest() {
+            Some(oldest) if oldest > target => {
+                return Err(format!(
+                    "Can't go back further than {} instructions",
+                    self.time - oldest
+                ))
+            }
+            Some(_) => {}
+            None => return Err("Nothing to go back to".to_string()),
+        }
+        let time = self
+            .rewind
+            .restore(target, &mut self.chip8)
+            .ok_or("Could not restore the snapshot")?;
+        self.time = time;
+        self.frame_cycles = 0;
+
+        // Key presses after the target never happened
+        self.inputs.retain(|&(t, ..)| t < target);
+        let inputs: Vec<_> = self
+            .inputs
+            .iter()
+            .filter(|&&(t, ..)| t >= time)
+            .copied()
+            .collect();
+        let mut inputs = inputs.into_iter().peekable();
+        while self.time < target {
+            while let Some((_, key, pressed)) =
+                inputs.next_if(|&(t, ..)| t == self.time)
+            {
+                self.press(key, pressed);
+            }
+            self.cycle().map_err(|e| e.to_string())?;
+        }
+
+        for i in 0..self.watches.len() {
+            self.watches[i].1 = self.value(self.watches[i].0);
+        }
+        Ok(())
+    }
+
+    /// Presses or releases a key of the keypad
+    fn press(&mut self, key: u8, pressed: bool) {
+        if pressed {
+            self.chip8.keypad.press(key);
+        } else {
+            self.chip8.keypad.release(key);
+        }
+    }
+
+    /// Executes an instruction, running a CALL until it returns
+    pub fn step_over(&mut self) -> Stop {
+        match self.instruction(self.chip8.cpu.pc) {
+            Some(Instruction::Call(_)) => {
+                let ret = self.chip8.cpu.pc.wrapping_add(2);
+                let depth = self.chip8.stack.addresses().len();
+                self.resume(|chip8| {
+                    chip8.cpu.pc == ret
+                        && chip8.stack.addresses().len() == depth
+                })
+            }
+            _ => self.step(),
+        }
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, or the limit of
+    /// instructions is reached
+    pub fn resume<F>(&mut self, until: F) -> Stop
+    where
+        F: Fn(&Chip8<D, K, A, R>) -> bool,
+    {
+        for _ in 0..self.limit {
+            let stop = self.step();
+            if stop != Stop::Done || until(&self.chip8) {
+                return stop;
+            }
+        }
+        Stop::Limit(self.limit)
+    }
+
+    /// Stops when PC reaches addr
+    pub fn add_breakpoint(&mut self, addr: Addr) {
+        self.breakpoints.insert(addr);
+    }
+
+    /// Stops when the watched value changes
+    pub fn add_watch(&mut self, watch: Watch) -> Result<(), String> {
+        if let Watch::Memory(addr) = watch {
+            if addr as usize >= self.chip8.mem.size() {
+                return Err(format!("Address {:#X} out of range", addr));
+            }
+        }
+        if !self.watches.iter().any(|&(w, _)| w == watch) {
+            let value = self.value(watch);
+            self.watches.push((watch, value));
+        }
+        Ok(())
+    }
+
+    /// Current value of a watch
+    fn value(&self, watch: Watch) -> u16 {
+        match watch {
+            // Watched addresses are checked when they are added
+            Watch::Memory(addr) => {
+                self.chip8.mem.read_byte(addr).unwrap_or(0) as u16
+            }
+            Watch::Register(x) => self.chip8.cpu.v[x as usize] as u16,
+            Watch::Index => self.chip8.cpu.i,
+        }
+    }
+
+    /// The instruction at addr, if it can be decoded
+    fn instruction(&self, addr: Addr) -> Option<Instruction> {
+        let bytes = self.chip8.mem.get_slice(addr, 2).ok()?;
+        decode((bytes[0] as u16) << 8 | bytes[1] as u16).ok()
+    }
+
+    /// Reads commands from input until it ends or quit is entered
+    pub fn repl<I: BufRead, W: Write>(
+        &mut self,
+        input: I,
+        mut output: W,
+    ) -> io::Result<()> {
+        let mut last = None;
+        let mut lines = input.lines();
+        loop {
+            write!(output, "(chip8) ")?;
+            output.flush()?;
+            let line = match lines.next() {
+                Some(line) => line?,
+                None => return Ok(()),
+            };
+
+            let command = if line.trim().is_empty() {
+                match last {
+                    Some(command) => command,
+                    None => continue,
+                }
+            } else {
+                match line.parse() {
+                    Ok(command) => command,
+                    Err(e) => {
+                        writeln!(output, "{}", e)?;
+                        continue;
+                    }
+                }
+            };
+            last = Some(command);
+
+            if !self.execute(command, &mut output)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Executes a command, writing its result to output
+    /// Returns false when the debugger should quit
+    pub fn execute<W: Write>(
+        &mut self,
+        command: Command,
+        output: &mut W,
+    ) -> io::Result<bool> {
+        match command {
+            Command::Step(n) => {
+                let mut stop = Stop::Done;
+                for _ in 0..n {
+                    stop = self.step();
+                    if stop != Stop::Done {
+                        break;
+                    }
+                }
+                self.stopped(stop, output)?;
+            }
+            Command::Next => {
+                let stop = self.step_over();
+                self.stopped(stop, output)?;
+            }
+            Command::ReverseStep(n) => match self.reverse_step(n as u64) {
+                Ok(()) => self.list(self.chip8.cpu.pc, 1, output)?,
+                Err(e) => writeln!(output, "{}", e)?,
+            },
+            Command::Continue => {
+                let stop = self.resume(|_| false);
+                self.stopped(stop, output)?;
+            }
+            Command::Break(addr) => {
+                self.add_breakpoint(addr);
+                writeln!(output, "Breakpoint at {:#05X}", addr)?;
+            }
+            Command::Delete(addr) => {
+                if !self.breakpoints.remove(&addr) {
+                    writeln!(output, "No breakpoint at {:#05X}", addr)?;
+                }
+            }
+            Command::Watch(watch) => match self.add_watch(watch) {
+                Ok(()) => writeln!(output, "Watching {}", watch)?,
+                Err(e) => writeln!(output, "{}", e)?,
+            },
+            Command::Unwatch(watch) => {
+                let count = self.watches.len();
+                self.watches.retain(|&(w, _)| w != watch);
+                if self.watches.len() == count {
+                    writeln!(output, "{} is not watched", watch)?;
+                }
+            }
+            Command::Info => {
+                for addr in &self.breakpoints {
+                    writeln!(output, "Breakpoint at {:#05X}", addr)?;
+                }
+                for (watch, value) in &self.watches {
+                    writeln!(output, "Watching {} = {:#X}", watch, value)?;
+                }
+            }
+            Command::Registers => self.registers(output)?,
+            Command::Stack => {
+                let addresses = self.chip8.stack.addresses();
+                if addresses.is_empty() {
+                    writeln!(output, "The stack is empty")?;
+                }
+                for (i, addr) in addresses.iter().enumerate().rev() {
+                    writeln!(output, "#{}: {:#05X}", i, addr)?;
+                }
+            }
+            Command::Memory(addr, n) => self.memory(addr, n, output)?,
+            Command::List(addr, n) => {
+                self.list(addr.unwrap_or(self.chip8.cpu.pc), n, output)?
+            }
+            Command::Screen => self.screen(output)?,
+            Command::Press(key) | Command::Release(key) => {
+                let pressed = command == Command::Press(key);
+                self.inputs.push((self.time, key, pressed));
+                self.press(key, pressed);
+            }
+            Command::Help => writeln!(output, "{}", HELP)?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Writes why the execution stopped and the next instruction
+    fn stopped<W: Write>(&self, stop: Stop, output: &mut W) -> io::Result<()> {
+        if stop != Stop::Done {
+            writeln!(output, "{}", stop)?;
+        }
+        self.list(self.chip8.cpu.pc, 1, output)
+    }
+
+    /// Writes the registers and timers
+    fn registers<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let cpu = &self.chip8.cpu;
+        writeln!(
+            output,
+            "PC: {:#05X}  I: {:#05X}  DT: {:#04X}  ST: {:#04X}",
+            cpu.pc,
+            cpu.i,
+            self.chip8.delay_timer(),
+            self.chip8.sound_timer()
+        )?;
+        for (i, values) in cpu.v.chunks(8).enumerate() {
+            let registers: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(j, value)| format!("V{:X}: {:#04X}", i * 8 + j, value))
+                .collect();
+            writeln!(output, "{}", registers.join("  "))?;
+        }
+        Ok(())
+    }
+
+    /// Writes n bytes of memory starting at addr, 16 bytes per line
+    fn memory<W: Write>(
+        &self,
+        addr: Addr,
+        n: u16,
+        output: &mut W,
+    ) -> io::Result<()> {
+        let size = self.chip8.mem.size();
+        if addr as usize >= size {
+            return writeln!(output, "Address {:#X} out of range", addr);
+        }
+        let n = (n as usize).min(size - addr as usize);
+        let bytes = self.chip8.mem.get_slice(addr, n).unwrap_or_default();
+        for (i, line) in bytes.chunks(16).enumerate() {
+            write!(output, "{:#06X}:", addr as usize + i * 16)?;
+            for byte in line {
+                write!(output, " {:02X}", byte)?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+
+    /// Disassembles n instructions starting at addr, marking PC and the
+    /// breakpoints
+    fn list<W: Write>(
+        &self,
+        mut addr: Addr,
+        n: u16,
+        output: &mut W,
+    ) -> io::Result<()> {
+        for _ in 0..n {
+            let bytes = match self.chip8.mem.get_slice(addr, 2) {
+                Ok(bytes) => bytes,
+                Err(_) => break,
+            };
+            let marker = if addr == self.chip8.cpu.pc {
+                "=>"
+            } else if self.breakpoints.contains(&addr) {
+                " *"
+            } else {
+                "  "
+            };
+            let opcode = (bytes[0] as u16) << 8 | bytes[1] as u16;
+            match decode(opcode) {
+                Ok(instruction) => writeln!(
+                    output,
+                    "{} {:#05X}: {:04X}  {}",
+                    marker, addr, opcode, instruction
+                )?,
+                Err(_) => writeln!(
+                    output,
+                    "{} {:#05X}: {:04X}  db {:#04X}, {:#04X}",
+                    marker, addr, opcode, bytes[0], bytes[1]
+                )?,
+            }
+            addr = addr.wrapping_add(2);
+        }
+        Ok(())
+    }
+
+    /// Writes the display, with a character for each combination of planes
+    fn screen<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        for row in self.chip8.frame.rows() {
+            for pixel in row {
+                let c = match pixel {
+                    0x0 => '.',
+                    0x1 => '#',
+                    0x2 => '+',
+                    _ => '@',
+                };
+                write!(output, "{}", c)?;
+            }
+            writeln!(output)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a number, in hexadecimal if it starts with 0x
+fn number(s: &str) -> Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| format!("Invalid number {}", s))
+}
@@ -0,0 +1,219 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::memory::XO_MEMORY_SIZE;
+use crate::opcode::{decode, Addr, Instruction};
+
+/// Address where ROMs are loaded
+pub const ORIGIN: Addr = 0x200;
+
+/// Column where the address comments start
+const COMMENT_COLUMN: usize = 28;
+
+/// A line of the disassembly
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line {
+    /// An instruction reached by the control flow, with its opcode
+    Code(u16, Instruction),
+    /// The 16 bits operand of an XO-CHIP 0xF000 NNNN
+    Long(Addr),
+    /// A byte never reached by the control flow
+    Data(u8),
+}
+
+/// A static disassembly of a ROM loaded at ORIGIN
+///
+/// Code is told apart from data by following jumps, calls and skips from
+/// ORIGIN, everything that is never reached is assumed to be data
+pub struct Disassembly {
+    /// Each line with the address it starts at
+    lines: Vec<(Addr, Line)>,
+    /// Addresses that are jumped to or called
+    code_labels: BTreeSet<Addr>,
+    /// Addresses that are loaded into I
+    data_labels: BTreeSet<Addr>,
+}
+
+impl Disassembly {
+    pub fn new(rom: &[u8]) -> Self {
+        let rom = &rom[..rom.len().min(XO_MEMORY_SIZE - ORIGIN as usize)];
+        let word = |addr: Addr| -> Option<u16> {
+            let i = (addr as usize).checked_sub(ORIGIN as usize)?;
+            if i + 1 < rom.len() {
+                Some((rom[i] as u16) << 8 | rom[i + 1] as u16)
+            } else {
+                None
+            }
+        };
+
+        let mut code = BTreeSet::new();
+        let mut code_labels = BTreeSet::new();
+        let mut data_labels = BTreeSet::new();
+        let mut pending = vec![ORIGIN];
+
+        while let Some(addr) = pending.pop() {
+            if code.contains(&addr) {
+                continue;
+            }
+            let instruction = match word(addr).map(decode) {
+                Some(Ok(instruction)) => instruction,
+                _ => continue,
+            };
+            code.insert(addr);
+
+            let next = addr.wrapping_add(2);
+            // Skipping over an 0xF000 NNNN skips 4 bytes
+            let skipped = match word(next) {
+                Some(0xF000) => next.wrapping_add(4),
+                _ => next.wrapping_add(2),
+            };
+
+            use Instruction::*;
+            match instruction {
+                Jp(target) => {
+                    code_labels.insert(target);
+                    pending.push(target);
+                }
+                Call(target) => {
+                    code_labels.insert(target);
+                    pending.push(target);
+                    pending.push(next);
+                }
+                // The offset is only known at runtime, but the target is
+                // usually the start of a table of jumps
+                JpV0(target) => {
+                    code_labels.insert(target);
+                    pending.push(target);
+                }
+                Ret | Exit => {}
+                SeVxByte(..) | SneVxByte(..) | SeVxVy(..) | SneVxVy(..)
+                | Skp(_) | Sknp(_) => {
+                    pending.push(next);
+                    pending.push(skipped);
+                }
+                LdI(target) => {
+                    data_labels.insert(target);
+                    pending.push(next);
+                }
+                LdILong => {
+                    if let Some(target) = word(next) {
+                        data_labels.insert(target);
+                    }
+                    pending.push(next.wrapping_add(2));
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        let mut lines = Vec::new();
+        let mut i = 0;
+        while i < rom.len() {
+            let addr = ORIGIN + i as Addr;
+            let opcode = word(addr).filter(|_| code.contains(&addr));
+            match opcode.map(|opcode| (opcode, decode(opcode))) {
+                Some((opcode, Ok(instruction))) => {
+                    lines.push((addr, Line::Code(opcode, instruction)));
+                    i += 2;
+                    if instruction == Instruction::LdILong {
+                        // An 0xF000 at the end of memory has no address
+                        // after it
+                        let at = addr.checked_add(2);
+                        if let Some((at, long)) = at.zip(at.and_then(word)) {
+                            lines.push((at, Line::Long(long)));
+                            i += 2;
+                        }
+                    }
+                }
+                _ => {
+                    lines.push((addr, Line::Data(rom[i])));
+                    i += 1;
+                }
+            }
+        }
+
+        Self {
+            lines,
+            code_labels,
+            data_labels,
+        }
+    }
+
+    /// Each line of the disassembly with the address it starts at
+    pub fn lines(&self) -> &[(Addr, Line)] {
+        &self.lines
+    }
+
+    /// Name of the label at addr, if there is a line starting there
+    pub fn label(&self, addr: Addr) -> Option<String> {
+        if self
+            .lines
+            .binary_search_by_key(&addr, |line| line.0)
+            .is_err()
+        {
+            return None;
+        }
+        if self.code_labels.contains(&addr) {
+            Some(format!("L{:03X}", addr))
+        } else if self.data_labels.contains(&addr) {
+            Some(format!("D{:03X}", addr))
+        } else {
+            None
+        }
+    }
+
+    /// An address operand, replaced by its label if there is one
+    fn operand(&self, addr: Addr) -> String {
+        self.label(addr).unwrap_or_else(|| format!("{:#05X}", addr))
+    }
+
+    /// The mnemonic of an instruction, with labels as operands
+    fn mnemonic(&self, instruction: Instruction) -> String {
+        use Instruction::*;
+
+        match instruction {
+            Jp(addr) => format!("JP {}", self.operand(addr)),
+            Call(addr) => format!("CALL {}", self.operand(addr)),
+            LdI(addr) => format!("LD I, {}", self.operand(addr)),
+            JpV0(addr) => format!("JP V0, {}", self.operand(addr)),
+            _ => instruction.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for &(addr, line) in &self.lines {
+            if let Some(label) = self.label(addr) {
+                writeln!(f, "{}:", label)?;
+            }
+            let (text, comment) = match line {
+                Line::Code(opcode, instruction) => {
+                    (self.mnemonic(instruction), format!("{:04X}", opcode))
+                }
+                Line::Long(long) => (
+                    format!("dw {}", self.operand(long)),
+                    format!("{:04X}", long),
+                ),
+                Line::Data(byte) => {
+                    (format!("db {:#010b}", byte), bitmap(byte))
+                }
+            };
+            writeln!(
+                f,
+                "    {:<width$}; {:#05X}: {}",
+                text,
+                addr,
+                comment,
+                width = COMMENT_COLUMN - 4
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A byte as a row of sprite pixels
+fn bitmap(byte: u8) -> String {
+    (0..8)
+        .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+        .collect()
+}
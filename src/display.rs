@@ -1,234 +1,113 @@
-use piston_window::*;
-
-/// For every pixel, there are SCALE real pixels
-const SCALE: usize = 10;
-
-/// Screen width and height without SCALE
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
-
-/// A Display for the CHIP-8
-pub trait Display {
-    /// Displays a pixel at (x, y) with size (8, height)
-    /// mem represents the start of memory indicated by the I register
-    /// Returns true if any bits where flipped
-    fn display(&mut self, x: u16, y: u16, height: u16, mem: &[u8]) -> bool;
-    /// Clears the display
-    fn clear(&mut self);
-    /// If the display is still open
-    fn is_open(&mut self) -> bool;
-    /// Updates the screen
-    fn update(&mut self);
-    /// Set if the display should keep updating
-    fn should_update(&mut self, update: bool);
-    /// Gets a pixel from the scaled up buffer
-    fn get_pixel(&self, x: u16, y: u16) -> u8;
-    /// Sets the pixels at coord taking into account the scale
-    fn set_pixel(&mut self, x: u16, y: u16, val: u8);
+use crate::framebuffer::FrameBuffer;
+use crate::keypad::Keypad;
+
+/// Screen width and height of the low resolution mode
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+/// Screen width and height of the SUPER-CHIP high resolution mode
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+
+/// XO-CHIP bitplanes, each pixel holds one bit for each of them
+pub const PLANES: [u8; 2] = [0b01, 0b10];
+
+/// Set on top of the planes of a pixel that is shown dimmed, like the ones
+/// left from older frames by anti-flicker
+pub const DIMMED: u8 = 0b100;
+
+/// Frontend actions bound to keys outside of the keypad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Selects the quick save slot
+    Slot(u8),
+    /// Saves the state to the selected slot
+    Save,
+    /// Loads the state from the selected slot
+    Load,
+    /// Starts or stops going back in time, while the key is held
+    Rewind(bool),
 }
 
-/// A display using the piston library
-pub struct Chip8Display {
-    screen: PistonWindow,
-    buffer: [[u8; WIDTH]; HEIGHT],
-    event: Option<Event>,
-    on: bool,
-}
-
-impl Chip8Display {
-    pub fn new() -> Self {
-        Self {
-            screen: WindowSettings::new(
-                "CHIP-8 Emulator",
-                Size::from(((WIDTH * SCALE) as u32, (HEIGHT * SCALE) as u32)),
-            )
-            .resizable(false)
-            .exit_on_esc(true)
-            .build()
-            .unwrap_or_else(|e| {
-                panic!("Could not create the emulator screen {}", e);
-            }),
-            buffer: [[0; WIDTH]; HEIGHT],
-            event: None,
-            on: true,
-        }
-    }
+/// Registers and timers a renderer can show next to the frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
 }
 
-impl Display for Chip8Display {
-    fn display(&mut self, x: u16, y: u16, height: u16, mem: &[u8]) -> bool {
-        let mut flipped = false;
-        for cy in 0..height {
-            let y_line = mem[cy as usize];
-            for cx in 0..8 {
-                let pixel = y_line & (0x80 >> cx);
-                if pixel != 0 {
-                    if self.buffer[x as usize][y as usize] == 1 {
-                        flipped = true
-                    }
-                    self.buffer[x as usize][y as usize] ^= 1;
-                }
-            }
-        }
-        flipped
-    }
-
-    fn clear(&mut self) {
-        for row in self.buffer.iter_mut() {
-            for col in row.iter_mut() {
-                *col = 0;
-            }
-        }
-    }
-
+/// Presents the frames of the CHIP-8 and feeds it with input
+pub trait Renderer {
+    /// Presents a frame, called once per 60 Hz tick
+    fn render(&mut self, frame: &FrameBuffer);
+    /// Receives the machine status, called right before render
+    fn status(&mut self, _status: &Status) {}
+    /// Shows a short message, like the result of a quick save hotkey
+    fn message(&mut self, _message: &str) {}
+    /// If the window is still open
     fn is_open(&mut self) -> bool {
-        self.event = self.screen.next();
-        self.event.is_some()
-    }
-
-    fn update(&mut self) {
-        if self.on {
-            // todo FIXME
-            let &mut Self {
-                ref mut screen,
-                ref buffer,
-                ..
-            } = self;
-            if let Some(ref e) = self.event {
-                screen.draw_2d(e, |c, g, _| {
-                    clear([1.0, 1.0, 1.0, 1.0], g);
-                    for i in 0..buffer.len() {
-                        for j in 0..buffer[0].len() {
-                            if i != 0 && j != 0 {
-                                let scale = SCALE as f64;
-                                rectangle(
-                                    [0.0, 0.0, 0.0, 1.0],
-                                    [
-                                        i as f64 * scale,
-                                        j as f64 * scale,
-                                        SCALE as f64,
-                                        SCALE as f64,
-                                    ],
-                                    c.transform,
-                                    g,
-                                );
-                            }
-                        }
-                    }
-                });
-            }
-        }
-    }
-
-    fn should_update(&mut self, update: bool) {
-        self.on = update;
+        true
     }
-
-    fn get_pixel(&self, x: u16, y: u16) -> u8 {
-        self.buffer[x as usize][y as usize]
+    /// If is_open waits for the next input event, which then sets the pace
+    /// of run instead of sleeping between frames
+    fn paced(&self) -> bool {
+        false
     }
-
-    fn set_pixel(&mut self, x: u16, y: u16, val: u8) {
-        self.buffer[x as usize][y as usize] = val;
+    /// Feeds the keypad with the input events received by the renderer
+    fn poll_keys(&mut self, _keypad: &mut dyn Keypad) {}
+    /// Gets the hotkey pressed since the last poll, if any
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        None
     }
 }
 
-impl Default for Chip8Display {
-    fn default() -> Self {
-        Self::new()
-    }
-}
+/// A renderer showing nothing, for tests and headless runs
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullRenderer;
 
-/// A simple display, used for tests and debugging
-pub struct TextDisplay {
-    buffer: [[u8; WIDTH]; HEIGHT],
-    on: bool,
+impl Renderer for NullRenderer {
+    fn render(&mut self, _frame: &FrameBuffer) {}
 }
 
+/// A simple display printed to the terminal, used for debugging
+#[cfg(feature = "std")]
+pub struct TextDisplay;
+
+#[cfg(feature = "std")]
 impl TextDisplay {
     pub fn new() -> Self {
-        Self {
-            buffer: [[0; WIDTH]; HEIGHT],
-            on: true,
-        }
-    }
-
-    #[allow(dead_code)]
-    pub fn debug_string(&self) -> [char; WIDTH * HEIGHT] {
-        let mut result: [char; WIDTH * HEIGHT] = [' '; WIDTH * HEIGHT];
-        for i in 0..WIDTH {
-            for j in 0..HEIGHT {
-                result[i + j * WIDTH] = match self.get_pixel(i as u16, j as u16)
-                {
-                    0x0 => ' ',
-                    _ => 'X',
-                }
-            }
-        }
-        result
+        Self
     }
 }
 
-impl Display for TextDisplay {
-    fn display(&mut self, x: u16, y: u16, height: u16, mem: &[u8]) -> bool {
-        let mut flipped = false;
-        for cy in 0..height {
-            let y_line = mem[cy as usize];
-            for cx in 0..8 {
-                let pixel = y_line & (0x80 >> cx);
-                if pixel != 0 {
-                    if self.buffer[x as usize][y as usize] == 1 {
-                        flipped = true
-                    }
-                    self.buffer[x as usize][y as usize] ^= 1;
+#[cfg(feature = "std")]
+impl Renderer for TextDisplay {
+    fn render(&mut self, frame: &FrameBuffer) {
+        // Clear the terminal before printing the frame
+        print!("\x1B[2J");
+        for row in frame.rows() {
+            for col in row.iter() {
+                match col {
+                    0x0 => print!("."),
+                    0x1 => print!("#"),
+                    0x2 => print!("+"),
+                    0x3 => print!("@"),
+                    _ => print!(":"),
                 }
             }
+            println!();
         }
-        flipped
-    }
-
-    fn clear(&mut self) {
-        for row in self.buffer.iter_mut() {
-            for col in row.iter_mut() {
-                *col = 0;
-            }
-        }
-    }
-
-    fn is_open(&mut self) -> bool {
-        true
-    }
-
-    fn update(&mut self) {
-        // todo fix
-        if self.on {
-            // Position the cursor at row 1, col 1
-            print!("\x1B[2J");
-            for row in self.buffer.iter() {
-                for col in row.iter() {
-                    match col {
-                        0x0 => print!("."),
-                        _ => print!("#"),
-                    }
-                }
-                println!("");
-            }
-        }
-    }
-
-    fn should_update(&mut self, update: bool) {
-        self.on = update;
-    }
-
-    fn get_pixel(&self, x: u16, y: u16) -> u8 {
-        self.buffer[x as usize][y as usize]
     }
 
-    fn set_pixel(&mut self, x: u16, y: u16, val: u8) {
-        self.buffer[x as usize][y as usize] = val;
+    fn message(&mut self, message: &str) {
+        println!("{}", message);
     }
 }
 
+#[cfg(feature = "std")]
 impl Default for TextDisplay {
     fn default() -> Self {
         Self::new()
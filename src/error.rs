@@ -0,0 +1,43 @@
+use core::fmt;
+
+use crate::stack::STACK_SIZE;
+
+/// Why the emulator can't go on
+///
+/// Errors raised by an instruction leave the machine as it was before it, so
+/// PC still points at the faulting opcode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// A CALL with the call stack full
+    StackOverflow,
+    /// A RET outside of any call
+    StackUnderflow,
+    /// An access past the end of the addressable memory
+    OutOfMemory(usize),
+    /// A ROM that doesn't fit in the memory after the interpreter
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Chip8Error::StackOverflow => {
+                write!(f, "Stack overflow, more than {} calls", STACK_SIZE)
+            }
+            Chip8Error::StackUnderflow => {
+                write!(f, "Stack underflow, return outside of a call")
+            }
+            Chip8Error::OutOfMemory(address) => {
+                write!(f, "Address {:#X} out of memory", address)
+            }
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "ROM of {} bytes doesn't fit in {} bytes of memory",
+                size, max
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Chip8Error {}
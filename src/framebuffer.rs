@@ -0,0 +1,188 @@
+use crate::display::{HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, PLANES, WIDTH};
+
+/// The pixels of the display, shared by every frontend
+///
+/// Each pixel holds one bit per XO-CHIP plane. Only the top left width x
+/// height pixels are on screen, the rest stays cleared
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameBuffer {
+    buffer: [[u8; HIRES_WIDTH]; HIRES_HEIGHT],
+    width: usize,
+    height: usize,
+    planes: u8,
+}
+
+impl FrameBuffer {
+    pub fn new() -> Self {
+        Self {
+            buffer: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+            width: WIDTH,
+            height: HEIGHT,
+            planes: PLANES[0],
+        }
+    }
+
+    /// XORs a sprite at (x, y) with size (width, height), following DXYN
+    /// width is either 8 or 16, and each row takes width / 8 bytes of mem
+    /// Each selected plane is drawn in order with its own sprite from mem
+    /// The sprite starts wrapped around the screen, and its pixels outside of
+    /// it wrap around if wrap is set, otherwise they are clipped
+    /// Returns true if any pixel was turned off
+    pub fn draw(
+        &mut self,
+        x: u16,
+        y: u16,
+        width: u16,
+        height: u16,
+        mem: &[u8],
+        wrap: bool,
+    ) -> bool {
+        let x = x as usize % self.width;
+        let y = y as usize % self.height;
+        let row_bytes = width as usize / 8;
+        let mut sprite = mem;
+        let mut flipped = false;
+        for plane in PLANES.iter() {
+            if self.planes & plane == 0 {
+                continue;
+            }
+            for cy in 0..height as usize {
+                if !wrap && y + cy >= self.height {
+                    break;
+                }
+                let py = (y + cy) % self.height;
+                for cx in 0..width as usize {
+                    if !wrap && x + cx >= self.width {
+                        break;
+                    }
+                    let px = (x + cx) % self.width;
+                    let y_line = sprite[cy * row_bytes + cx / 8];
+                    let pixel = y_line & (0x80 >> (cx % 8));
+                    if pixel != 0 {
+                        if self.buffer[py][px] & plane != 0 {
+                            flipped = true
+                        }
+                        self.buffer[py][px] ^= plane;
+                    }
+                }
+            }
+            sprite = &sprite[row_bytes * height as usize..];
+        }
+        flipped
+    }
+
+    /// Clears the selected planes
+    pub fn clear(&mut self) {
+        for row in self.buffer.iter_mut() {
+            for col in row.iter_mut() {
+                *col &= !self.planes;
+            }
+        }
+    }
+
+    /// Switches between the 64x32 and the 128x64 resolutions, clearing it
+    pub fn set_hires(&mut self, hires: bool) {
+        let (width, height) = if hires {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (WIDTH, HEIGHT)
+        };
+        self.width = width;
+        self.height = height;
+        self.buffer = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+    }
+
+    /// Selects the XO-CHIP planes affected by drawing, clearing and scrolling
+    pub fn set_planes(&mut self, planes: u8) {
+        self.planes = planes & 0b11;
+    }
+
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Current width and height
+    pub fn size(&self) -> (u16, u16) {
+        (self.width as u16, self.height as u16)
+    }
+
+    pub fn get_pixel(&self, x: u16, y: u16) -> u8 {
+        self.buffer[y as usize][x as usize]
+    }
+
+    pub fn set_pixel(&mut self, x: u16, y: u16, val: u8) {
+        self.buffer[y as usize][x as usize] = val;
+    }
+
+    /// Scrolls the selected planes down by n pixels
+    pub fn scroll_down(&mut self, n: u16) {
+        let (width, height) = self.size();
+        for y in (0..height).rev() {
+            for x in 0..width {
+                let val = if y >= n { self.get_pixel(x, y - n) } else { 0 };
+                self.scroll_pixel(x, y, val);
+            }
+        }
+    }
+
+    /// Scrolls the selected planes up by n pixels
+    pub fn scroll_up(&mut self, n: u16) {
+        let (width, height) = self.size();
+        for y in 0..height {
+            for x in 0..width {
+                let val = if y + n < height {
+                    self.get_pixel(x, y + n)
+                } else {
+                    0
+                };
+                self.scroll_pixel(x, y, val);
+            }
+        }
+    }
+
+    /// Scrolls the selected planes right by n pixels
+    pub fn scroll_right(&mut self, n: u16) {
+        let (width, height) = self.size();
+        for y in 0..height {
+            for x in (0..width).rev() {
+                let val = if x >= n { self.get_pixel(x - n, y) } else { 0 };
+                self.scroll_pixel(x, y, val);
+            }
+        }
+    }
+
+    /// Scrolls the selected planes left by n pixels
+    pub fn scroll_left(&mut self, n: u16) {
+        let (width, height) = self.size();
+        for y in 0..height {
+            for x in 0..width {
+                let val = if x + n < width {
+                    self.get_pixel(x + n, y)
+                } else {
+                    0
+                };
+                self.scroll_pixel(x, y, val);
+            }
+        }
+    }
+
+    /// Sets the selected planes of a pixel to the ones of val
+    fn scroll_pixel(&mut self, x: u16, y: u16, val: u8) {
+        let pixel = self.get_pixel(x, y);
+        self.set_pixel(x, y, (pixel & !self.planes) | (val & self.planes));
+    }
+
+    /// The rows on screen, from the top
+    pub fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let width = self.width;
+        self.buffer[..self.height]
+            .iter()
+            .map(move |row| &row[..width])
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,171 @@
+use crate::audio::Audio;
+use crate::chip8::Chip8;
+use crate::display::Renderer;
+use crate::error::Chip8Error;
+use crate::keypad::{Keypad, KEY_COUNT};
+use crate::random::Random;
+
+/// A key pressed or released at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Input {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// How long a headless run lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// Runs a number of instructions
+    Cycles(u64),
+    /// Runs a number of 60 Hz frames
+    Frames(u64),
+}
+
+/// How far a headless run went
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub cycles: u64,
+    pub frames: u64,
+    /// The error that stopped the run early, if any
+    pub error: Option<Chip8Error>,
+}
+
+/// Parses an input script, sorted by frame
+///
+/// Each line is `<frame> press <key>` or `<frame> release <key>`, with the
+/// key in hexadecimal, and `#` starts a comment
+pub fn parse_script(script: &str) -> Result<Vec<Input>, String> {
+    let mut inputs = Vec::new();
+    for (i, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {}", i + 1, message);
+
+        let (frame, action, key) = match words[..] {
+            [frame, action, key] => (frame, action, key),
+            _ => return Err(error("Expected <frame> press|release <key>")),
+        };
+        let frame = frame.parse().map_err(|_| error("Invalid frame"))?;
+        let pressed = match action {
+            "press" => true,
+            "release" => false,
+            _ => return Err(error("Expected press or release")),
+        };
+        let key = match u8::from_str_radix(key, 16) {
+            Ok(key) if (key as usize) < KEY_COUNT => key,
+            _ => return Err(error("Invalid key")),
+        };
+
+        inputs.push(Input {
+            frame,
+            key,
+            pressed,
+        });
+    }
+
+    inputs.sort_by_key(|input| input.frame);
+    Ok(inputs)
+}
+
+/// Runs chip8 as fast as possible until the limit is reached, the program
+/// exits or an instruction fails, feeding the keypad with the inputs at the
+/// start of their frame
+pub fn run<D, K, A, R>(
+    chip8: &mut Chip8<D, K, A, R>,
+    limit: Limit,
+    inputs: &[Input],
+) -> Progress
+where
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
+{
+    let mut progress = Progress::default();
+    let mut inputs = inputs.iter().peekable();
+    let done = |progress: &Progress| match limit {
+        Limit::Cycles(cycles) => progress.cycles >= cycles,
+        Limit::Frames(frames) => progress.frames >= frames,
+    };
+
+    while !done(&progress) && !chip8.exited() {
+        while let Some(input) =
+            inputs.next_if(|input| input.frame <= progress.frames)
+        {
+            if input.pressed {
+                chip8.keypad.press(input.key);
+            } else {
+                chip8.keypad.release(input.key);
+            }
+        }
+
+        for _ in 0..chip8.frame_cycles() {
+            if done(&progress) {
+                // The limit was reached in the middle of the frame
+                return progress;
+            }
+            if !chip8.can_cycle() {
+                break;
+            }
+            if let Err(e) = chip8.cycle() {
+                progress.error = Some(e);
+                return progress;
+            }
+            progress.cycles += 1;
+        }
+        chip8.end_frame();
+        progress.frames += 1;
+    }
+
+    progress
+}
+
+/// Dumps the registers, timers and stack of chip8 as JSON
+pub fn registers_json<D, K, A, R>(
+    chip8: &Chip8<D, K, A, R>,
+    progress: Progress,
+) -> String
+where
+    D: Renderer + Default,
+    K: Keypad + Default,
+    A: Audio + Default,
+    R: Random + Default,
+{
+    let list = |values: Vec<String>| values.join(", ");
+    let v = list(chip8.cpu.v.iter().map(|v| v.to_string()).collect());
+    let stack = list(
+        chip8
+            .stack
+            .addresses()
+            .iter()
+            .map(|addr| addr.to_string())
+            .collect(),
+    );
+
+    // Error messages have no quotes or backslashes to escape
+    let error = match progress.error {
+        Some(e) => format!("\"{}\"", e),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\n  \"pc\": {},\n  \"i\": {},\n  \"v\": [{}],\n  \
+         \"stack\": [{}],\n  \"delay_timer\": {},\n  \"sound_timer\": {},\n  \
+         \"exited\": {},\n  \"error\": {},\n  \"cycles\": {},\n  \
+         \"frames\": {}\n}}\n",
+        chip8.cpu.pc,
+        chip8.cpu.i,
+        v,
+        stack,
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        chip8.exited(),
+        error,
+        progress.cycles,
+        progress.frames
+    )
+}
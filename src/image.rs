@@ -0,0 +1,107 @@
+use std::io::{self, Write};
+
+use crate::framebuffer::FrameBuffer;
+
+/// Gray level of a pixel by its planes, matching the colors of the window
+const GRAYS: [u8; 4] = [0xFF, 0x00, 0x99, 0x4C];
+
+/// Largest block of data a stored deflate block can hold
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Writes the frame as a plain PBM, where every lit pixel is black
+pub fn write_pbm<W: Write>(frame: &FrameBuffer, mut w: W) -> io::Result<()> {
+    let (width, height) = frame.size();
+    writeln!(w, "P1")?;
+    writeln!(w, "{} {}", width, height)?;
+    for row in frame.rows() {
+        let row: Vec<&str> = row
+            .iter()
+            .map(|&pixel| if pixel != 0 { "1" } else { "0" })
+            .collect();
+        writeln!(w, "{}", row.join(" "))?;
+    }
+    w.flush()
+}
+
+/// Writes the frame as an 8 bit grayscale PNG
+pub fn write_png<W: Write>(frame: &FrameBuffer, mut w: W) -> io::Result<()> {
+    let (width, height) = frame.size();
+
+    // Each row starts with its filter, which is always none
+    let mut pixels = Vec::with_capacity((width as usize + 1) * height as usize);
+    for row in frame.rows() {
+        pixels.push(0);
+        pixels.extend(row.iter().map(|&pixel| GRAYS[pixel as usize & 0b11]));
+    }
+
+    let mut header = Vec::new();
+    header.extend(&(width as u32).to_be_bytes());
+    header.extend(&(height as u32).to_be_bytes());
+    // Bit depth 8, grayscale, deflate, no filters and no interlacing
+    header.extend(&[8, 0, 0, 0, 0]);
+
+    w.write_all(b"\x89PNG\r\n\x1A\n")?;
+    write_chunk(&mut w, b"IHDR", &header)?;
+    write_chunk(&mut w, b"IDAT", &zlib_stored(&pixels))?;
+    write_chunk(&mut w, b"IEND", &[])?;
+    w.flush()
+}
+
+/// Writes a PNG chunk with its length and checksum
+fn write_chunk<W: Write>(
+    w: &mut W,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data));
+    w.write_all(&crc.to_be_bytes())
+}
+
+/// Wraps data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window and no compression
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        stream.extend(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend(&len.to_le_bytes());
+        stream.extend(&(!len).to_le_bytes());
+        stream.extend(block);
+    }
+    stream.extend(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// The CRC-32 checksum used by PNG chunks
+fn crc32<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// The Adler-32 checksum used by zlib streams
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
@@ -0,0 +1,98 @@
+/// Number of keys on the CHIP-8 hexadecimal keypad
+pub const KEY_COUNT: usize = 16;
+
+/// Keys of the left of a QWERTY keyboard along with the CHIP-8 key they
+/// press, laid out like the keypad
+///
+/// ```text
+/// Keyboard    CHIP-8
+/// 1 2 3 4     1 2 3 C
+/// Q W E R     4 5 6 D
+/// A S D F     7 8 9 E
+/// Z X C V     A 0 B F
+/// ```
+pub const LAYOUT: [(char, u8); KEY_COUNT] = [
+    ('1', 0x1),
+    ('2', 0x2),
+    ('3', 0x3),
+    ('4', 0xC),
+    ('q', 0x4),
+    ('w', 0x5),
+    ('e', 0x6),
+    ('r', 0xD),
+    ('a', 0x7),
+    ('s', 0x8),
+    ('d', 0x9),
+    ('f', 0xE),
+    ('z', 0xA),
+    ('x', 0x0),
+    ('c', 0xB),
+    ('v', 0xF),
+];
+
+/// Maps a character of the keyboard to the CHIP-8 keypad with LAYOUT,
+/// ignoring the case
+pub fn keypad_key(c: char) -> Option<u8> {
+    let c = c.to_ascii_lowercase();
+    LAYOUT.iter().find(|&&(k, _)| k == c).map(|&(_, key)| key)
+}
+
+/// The 16-key hexadecimal keypad of the CHIP-8
+///
+/// Keys are identified by their hexadecimal value, from 0x0 to 0xF
+pub trait Keypad {
+    /// If the key is currently held down
+    fn is_pressed(&self, key: u8) -> bool;
+    /// Marks a key as held down
+    fn press(&mut self, key: u8);
+    /// Marks a key as released
+    fn release(&mut self, key: u8);
+    /// Takes the last key that was pressed and then released, if any
+    fn take_released(&mut self) -> Option<u8>;
+}
+
+/// A keypad that keeps the state of each key in memory
+#[derive(Debug)]
+pub struct HexKeypad {
+    /// If each key is held down
+    keys: [bool; KEY_COUNT],
+    /// Last key that was pressed and released
+    released: Option<u8>,
+}
+
+impl HexKeypad {
+    pub fn new() -> Self {
+        Self {
+            keys: [false; KEY_COUNT],
+            released: None,
+        }
+    }
+}
+
+impl Keypad for HexKeypad {
+    fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xF) as usize]
+    }
+
+    fn press(&mut self, key: u8) {
+        self.keys[(key & 0xF) as usize] = true;
+    }
+
+    fn release(&mut self, key: u8) {
+        let key = key & 0xF;
+        if self.keys[key as usize] {
+            self.keys[key as usize] = false;
+            self.released = Some(key);
+        }
+    }
+
+    fn take_released(&mut self) -> Option<u8> {
+        self.released.take()
+    }
+}
+
+impl Default for HexKeypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,79 @@
+//! A CHIP-8, SUPER-CHIP and XO-CHIP interpreter
+//!
+//! The core has no windowing dependency: Chip8 draws to its FrameBuffer,
+//! and a frontend implements Renderer, Keypad and Audio and drives Chip8 one
+//! frame or one instruction at a time. The piston window is behind the
+//! `piston` feature, on by default.
+//!
+//! ```
+//! use chip8_emulator::{Chip8, Quirks};
+//!
+//! let mut chip8: Chip8 = Chip8::new(Quirks::default());
+//! chip8.load_rom(&[0x60, 0x2A, 0x00, 0xFD]).unwrap(); // LD V0, 42 / EXIT
+//! chip8.frame().unwrap();
+//! assert_eq!(chip8.cpu.v[0], 42);
+//! ```
+//!
+//! Without the default `std` feature the core is `no_std`, only needing an
+//! allocator, and the tools, the text display and the wall-clock `run` are
+//! left out.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod antiflicker;
+#[cfg(feature = "std")]
+pub mod asm;
+pub mod audio;
+pub mod chip8;
+pub mod cpu;
+#[cfg(feature = "std")]
+pub mod debugger;
+#[cfg(feature = "std")]
+pub mod disasm;
+pub mod display;
+pub mod error;
+pub mod framebuffer;
+#[cfg(feature = "std")]
+pub mod headless;
+#[cfg(feature = "std")]
+pub mod image;
+pub mod keypad;
+pub mod memory;
+pub mod opcode;
+pub mod palette;
+#[cfg(feature = "piston")]
+pub mod piston;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod sprites;
+pub mod stack;
+pub mod state;
+pub mod trace;
+#[cfg(all(feature = "tui", unix))]
+pub mod tui;
+
+pub use crate::antiflicker::{AntiFlicker, FrameBlender};
+pub use crate::audio::{Audio, NullAudio};
+pub use crate::chip8::{Chip8, Platform, StepOutcome};
+pub use crate::cpu::Cpu;
+#[cfg(feature = "std")]
+pub use crate::display::TextDisplay;
+pub use crate::display::{NullRenderer, Renderer};
+pub use crate::error::Chip8Error;
+pub use crate::framebuffer::FrameBuffer;
+pub use crate::keypad::{HexKeypad, Keypad};
+pub use crate::memory::Memory;
+pub use crate::opcode::{decode, Instruction, Opcode};
+pub use crate::palette::Palette;
+#[cfg(feature = "piston")]
+pub use crate::piston::Chip8Display;
+pub use crate::quirks::Quirks;
+pub use crate::random::{Random, SplitMix};
+pub use crate::stack::Stack;
+pub use crate::trace::{Step, Tracer};
+
+#[cfg(all(test, feature = "std"))]
+mod tests;
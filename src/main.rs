@@ -1,42 +1,339 @@
-use std::fs::File;
-use std::io::Read;
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::process;
+use std::str::FromStr;
 
-use crate::chip8::Chip8;
-use crate::display::{Chip8Display, TextDisplay};
+use chip8_emulator::antiflicker::{AntiFlicker, FrameBlender};
+use chip8_emulator::audio::{Audio, NullAudio, TerminalBell};
+use chip8_emulator::chip8::{Chip8, Platform, DEFAULT_SPEED};
+use chip8_emulator::display::{Renderer, TextDisplay};
+use chip8_emulator::keypad::HexKeypad;
+use chip8_emulator::palette::Palette;
+#[cfg(feature = "piston")]
+use chip8_emulator::piston::{Chip8Display, DEFAULT_SCALE};
+use chip8_emulator::quirks::Quirks;
+use chip8_emulator::random::SplitMix;
+use chip8_emulator::trace::{TraceFormat, WriteTracer};
+#[cfg(all(feature = "tui", unix))]
+use chip8_emulator::tui::TerminalDisplay;
 
-mod chip8;
-mod cpu;
-mod display;
-mod memory;
-mod opcode;
-mod sprites;
-mod stack;
+const USAGE: &str = "\
+Usage: chip8_emulator <rom> [options]
 
-#[cfg(test)]
-mod tests;
+Options:
+    --speed <n>         instructions per second, 700 by default
+    --quirks <preset>   vip, chip48, schip or modern, modern by default
+    --scale <n>         size in pixels of each pixel of the window, 10 by
+                        default
+    --display <name>    piston, tui or text, piston by default when built
+                        with the piston feature
+    --palette <name>    classic, green, amber, octo or 2 or 4 hex colors
+                        like #000000,#33FF33, classic by default
+    --fade <n>          share of the color kept by turned off pixels each
+                        frame, from 0 to 0.95, 0 by default
+    --scanlines         darken the bottom of every row of pixels
+    --grid              outline every pixel
+    --antiflicker <m>   `or` to combine the last frames or `blend` to show
+                        the older ones dimmed, with an optional frame count
+                        from 1 to 8 after a colon, 2 by default, like or:3,
+                        off by default
+    --trace <file>      write every executed instruction to a file
+    --trace-format <f>  text, csv or jsonl, text by default
+    --mute              don't ring the terminal bell when the buzzer sounds
+    --seed <n>          seed of the random number generator, random by
+                        default
+    --platform <name>   chip8, schip or xochip, chip8 by default
 
-fn main() {
-    let file_name = "data/TETRIS";
-    let mut file = match File::open(file_name) {
-        Ok(file) => file,
-        Err(_) => {
-            eprintln!("Could not open file {}", file_name);
-            return;
+Options are also read from <rom>.cfg when it exists, before the ones of the
+command line";
+
+/// Frontends the emulator can be displayed on
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Frontend {
+    Piston,
+    Tui,
+    Text,
+}
+
+/// Settings of the emulator given on the command line or next to the ROM
+struct Options {
+    rom: String,
+    speed: u32,
+    quirks: Quirks,
+    scale: Option<usize>,
+    frontend: Frontend,
+    palette: Palette,
+    fade: f32,
+    scanlines: bool,
+    grid: bool,
+    antiflicker: Option<AntiFlicker>,
+    trace: Option<String>,
+    trace_format: TraceFormat,
+    mute: bool,
+    seed: Option<u64>,
+    platform: Platform,
+}
+
+/// Prints an error and exits
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+/// Parses the number given to an option
+fn number<T: FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number {} for {}", value, arg))
+}
+
+impl Options {
+    fn new() -> Self {
+        Self {
+            rom: String::new(),
+            speed: DEFAULT_SPEED,
+            quirks: Quirks::default(),
+            scale: None,
+            frontend: if cfg!(feature = "piston") {
+                Frontend::Piston
+            } else {
+                Frontend::Text
+            },
+            palette: Palette::default(),
+            fade: 0.0,
+            scanlines: false,
+            grid: false,
+            antiflicker: None,
+            trace: None,
+            trace_format: TraceFormat::Text,
+            mute: false,
+            seed: None,
+            platform: Platform::Chip8,
         }
-    };
-    let mut data = Vec::<u8>::new();
-    match file.read_to_end(&mut data) {
-        Ok(size) => println!("Read file {} with {} bytes", file_name, size),
-        Err(_) => {
-            eprintln!("Could not read file {}", file_name);
-            return;
+    }
+
+    /// Applies args over the options, the first free argument being the ROM
+    fn parse<I>(&mut self, mut args: I) -> Result<(), String>
+    where
+        I: Iterator<Item = String>,
+    {
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--speed" => self.speed = number(&arg, &value()?)?,
+                "--quirks" => self.quirks = value()?.parse()?,
+                "--scale" => self.scale = Some(number(&arg, &value()?)?),
+                "--display" => {
+                    self.frontend = match value()?.as_str() {
+                        "piston" => Frontend::Piston,
+                        "tui" => Frontend::Tui,
+                        "text" => Frontend::Text,
+                        name => {
+                            return Err(format!("Unknown display {}", name))
+                        }
+                    }
+                }
+                "--palette" => self.palette = value()?.parse()?,
+                "--fade" => self.fade = number(&arg, &value()?)?,
+                "--scanlines" => self.scanlines = true,
+                "--grid" => self.grid = true,
+                "--antiflicker" => {
+                    self.antiflicker = match value()?.as_str() {
+                        "off" => None,
+                        mode => Some(mode.parse()?),
+                    }
+                }
+                "--trace" => self.trace = Some(value()?),
+                "--trace-format" => self.trace_format = value()?.parse()?,
+                "--mute" => self.mute = true,
+                "--seed" => self.seed = Some(number(&arg, &value()?)?),
+                "--platform" => self.platform = value()?.parse()?,
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ if self.rom.is_empty() && !arg.starts_with('-') => {
+                    self.rom = arg
+                }
+                _ => {
+                    return Err(format!(
+                        "Unexpected argument {}\n\n{}",
+                        arg, USAGE
+                    ))
+                }
+            }
         }
+        Ok(())
     }
+}
 
-    let mut chip8 = Chip8::<Chip8Display>::new().debug();
+/// Parses the command line, over the options of the ROM
+fn parse_args() -> Options {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = Options::new();
+    options
+        .parse(args.iter().cloned())
+        .unwrap_or_else(|e| fail(&e));
+    if options.rom.is_empty() {
+        fail(USAGE);
+    }
 
-    chip8.load_rom(&data);
-    chip8.run();
+    // Options of the ROM, one or more per line with their values, with #
+    // comments
+    let path = format!("{}.cfg", options.rom);
+    if let Ok(config) = fs::read_to_string(&path) {
+        let mut defaults = Options::new();
+        // The ROM is already known, so free arguments are rejected
+        defaults.rom = options.rom;
+        for (n, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            defaults
+                .parse(line.split_whitespace().map(String::from))
+                .unwrap_or_else(|e| {
+                    fail(&format!("{}:{}: {}", path, n + 1, e))
+                });
+        }
+        defaults.rom = String::new();
+        defaults
+            .parse(args.into_iter())
+            .unwrap_or_else(|e| fail(&e));
+        options = defaults;
+    }
+    options
+}
 
-    // chip8.dump();
+/// Reads the ROM, checking that it fits in memory
+fn read_rom(options: &Options) -> Vec<u8> {
+    let data = fs::read(&options.rom).unwrap_or_else(|e| {
+        fail(&format!("Could not read file {}: {}", options.rom, e))
+    });
+    let max_size = options.platform.max_rom_size();
+    if data.is_empty() {
+        fail(&format!("{} is empty", options.rom));
+    }
+    if data.len() > max_size {
+        fail(&format!(
+            "{} is {} bytes long, but {} ROMs can't be longer than {} bytes",
+            options.rom,
+            data.len(),
+            options.platform,
+            max_size
+        ));
+    }
+    data
+}
+
+/// Runs the ROM until the display is closed or the program exits
+fn emulate<D, A>(options: &Options, data: &[u8], display: D)
+where
+    D: Renderer + Default,
+    A: Audio + Default,
+{
+    let mut chip8 = Chip8::<D, HexKeypad, A, SplitMix>::with_display(
+        options.quirks,
+        display,
+    )
+    .platform(options.platform)
+    .speed(options.speed)
+    .state_path(&options.rom);
+    if let Some(seed) = options.seed {
+        chip8 = chip8.seed(seed);
+    }
+    if let Some(path) = &options.trace {
+        let file = File::create(path).unwrap_or_else(|e| {
+            fail(&format!("Could not create file {}: {}", path, e))
+        });
+        chip8 = chip8.tracer(WriteTracer::with_format(
+            BufWriter::new(file),
+            options.trace_format,
+        ));
+    }
+
+    if let Err(e) = chip8.load_rom(data) {
+        let message = format!("Could not load {}: {}", options.rom, e);
+        drop(chip8);
+        fail(&message);
+    }
+    if let Err(e) = chip8.run() {
+        let opcode = match chip8.opcode() {
+            Ok(opcode) => format!("{:04X}", opcode),
+            Err(_) => "????".to_string(),
+        };
+        let message =
+            format!("Crashed at {:#05X} on {}: {}", chip8.cpu.pc, opcode, e);
+        // Give the terminal back to the shell before printing
+        drop(chip8);
+        fail(&message);
+    }
+}
+
+/// Runs the ROM on display, combining the last frames if asked
+fn emulate_on<D: Renderer + Default>(
+    options: &Options,
+    data: &[u8],
+    display: D,
+) {
+    if let Some(mode) = options.antiflicker {
+        let display = FrameBlender::new(display, mode);
+        emulate_with_audio(options, data, display);
+    } else {
+        emulate_with_audio(options, data, display);
+    }
+}
+
+/// Runs the ROM on display, with the buzzer unless muted
+fn emulate_with_audio<D: Renderer + Default>(
+    options: &Options,
+    data: &[u8],
+    display: D,
+) {
+    if options.mute {
+        emulate::<D, NullAudio>(options, data, display);
+    } else {
+        emulate::<D, TerminalBell>(options, data, display);
+    }
+}
+
+#[cfg(feature = "piston")]
+fn emulate_piston(options: &Options, data: &[u8]) {
+    let scale = options.scale.unwrap_or(DEFAULT_SCALE);
+    let display = Chip8Display::with_scale(scale)
+        .palette(options.palette)
+        .fade(options.fade)
+        .scanlines(options.scanlines)
+        .grid(options.grid);
+    emulate_on(options, data, display);
+}
+
+#[cfg(not(feature = "piston"))]
+fn emulate_piston(_options: &Options, _data: &[u8]) {
+    fail("Built without the piston feature, only --display text is available");
+}
+
+#[cfg(all(feature = "tui", unix))]
+fn emulate_tui(options: &Options, data: &[u8]) {
+    let display = TerminalDisplay::open().unwrap_or_else(|e| {
+        fail(&format!("Could not set up the terminal: {}", e))
+    });
+    emulate_on(options, data, display);
+}
+
+#[cfg(not(all(feature = "tui", unix)))]
+fn emulate_tui(_options: &Options, _data: &[u8]) {
+    fail("Built without the tui feature, which needs a Unix terminal");
+}
+
+fn main() {
+    let options = parse_args();
+    let data = read_rom(&options);
+
+    match options.frontend {
+        Frontend::Piston => emulate_piston(&options, &data),
+        Frontend::Tui => emulate_tui(&options, &data),
+        Frontend::Text => emulate_on(&options, &data, TextDisplay::new()),
+    }
 }
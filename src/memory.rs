@@ -1,22 +1,42 @@
-use crate::sprites::SPRITES;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::error::Chip8Error;
+use crate::sprites::{BIG_SPRITES, SPRITES};
+
+/// Memory size of the original CHIP-8
+pub const MEMORY_SIZE: usize = 0x1000;
+
+/// Memory size of the XO-CHIP
+pub const XO_MEMORY_SIZE: usize = 0x10000;
+
+/// Where programs are loaded
+/// The first 512 bytes is where the original interpreter was located
+pub const PROGRAM_START: u16 = 0x200;
 
 pub struct Memory {
-    /// Actual memory
-    pub mem: [u8; 4096],
+    /// Actual memory, sized for the platform
+    pub mem: Vec<u8>,
 
     /// Offset for the start of a program
-    /// The first 512 bytes is where the original interpreter was located
     pub offset: u16,
 }
 
 impl Memory {
     pub fn new() -> Self {
+        Self::with_size(MEMORY_SIZE)
+    }
+
+    /// Creates a memory with size addressable bytes, up to XO_MEMORY_SIZE
+    pub fn with_size(size: usize) -> Self {
         let mut memory = Memory {
-            mem: [0; 4096],
-            offset: 0x200,
+            mem: vec![0; size.min(XO_MEMORY_SIZE)],
+            offset: PROGRAM_START,
         };
 
-        // Initialize memory at 0x0000 with pre-defined sprites
+        // Initialize memory at 0x0000 with pre-defined sprites, followed by
+        // the SUPER-CHIP high resolution sprites
         let mut i = 0;
         for sprite in &SPRITES {
             for byte in sprite {
@@ -24,35 +44,77 @@ impl Memory {
                 i += 1;
             }
         }
+        for sprite in &BIG_SPRITES {
+            for byte in sprite {
+                memory.mem[i] = *byte;
+                i += 1;
+            }
+        }
 
         memory
     }
 
     /// Writes a byte to a memory region
-    pub fn write_byte(&mut self, address: u16, value: u8) {
-        self.mem[address as usize] = value;
+    pub fn write_byte(
+        &mut self,
+        address: u16,
+        value: u8,
+    ) -> Result<(), Chip8Error> {
+        self.get_slice_mut(address, 1)?[0] = value;
+        Ok(())
     }
 
     /// Reads a byte from a memory region
-    pub fn read_byte(&mut self, address: u16) -> u8 {
-        self.mem[address as usize]
+    pub fn read_byte(&self, address: u16) -> Result<u8, Chip8Error> {
+        Ok(self.get_slice(address, 1)?[0])
     }
 
     /// Gets a slice from memory starting at i up to i + n
-    pub fn get_slice(&self, i: u16, n: u16) -> &[u8] {
-        &self.mem[i as usize..(i + n) as usize]
+    pub fn get_slice(&self, i: u16, n: usize) -> Result<&[u8], Chip8Error> {
+        let range = self.range(i, n)?;
+        Ok(&self.mem[range])
     }
 
-    #[allow(dead_code)]
+    /// Gets a mutable slice from memory starting at i up to i + n
+    pub fn get_slice_mut(
+        &mut self,
+        i: u16,
+        n: usize,
+    ) -> Result<&mut [u8], Chip8Error> {
+        let range = self.range(i, n)?;
+        Ok(&mut self.mem[range])
+    }
+
+    /// Addressable memory size
+    pub fn size(&self) -> usize {
+        self.mem.len()
+    }
+
+    /// Checks that i up to i + n is addressable
+    fn range(&self, i: u16, n: usize) -> Result<Range<usize>, Chip8Error> {
+        let (start, end) = (i as usize, i as usize + n);
+        if end > self.size() {
+            return Err(Chip8Error::OutOfMemory(start.max(self.size())));
+        }
+        Ok(start..end)
+    }
+
+    #[cfg(feature = "std")]
     pub fn dump(&self) {
         for (i, b) in self.mem.iter().enumerate() {
             if i % 16 == 0 {
-                print!("\n0x{:0>3X}: ", i);
+                print!("\n0x{:0>4X}: ", i);
             } else if i % 8 == 0 {
                 print!(" ");
             }
             print!("{}{:0>2X}", if i % 2 == 0 { " " } else { "" }, b);
         }
-        println!("");
+        println!();
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
     }
 }
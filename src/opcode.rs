@@ -1,7 +1,7 @@
-use std::fmt;
-use std::ops;
+use core::fmt;
+use core::ops;
 
-#[allow(dead_code)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Nibble {
     A = 0,
     B = 1,
@@ -90,3 +90,343 @@ impl ops::Index<Nibble> for Opcode {
         }
     }
 }
+
+/// A V register, from V0 to VF
+pub type Reg = u8;
+
+/// A memory address
+pub type Addr = u16;
+
+/// A decoded instruction
+///
+/// Covers the original CHIP-8 instructions along with the SUPER-CHIP and
+/// XO-CHIP extensions, the opcode each one is decoded from is on its docs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0x0000 -> Does nothing
+    Nop,
+    /// 0x0NNN -> Machine code routine at NNN, ignored
+    Sys(Addr),
+    /// 0x00CN -> Scroll display down by N pixels
+    Scd(u8),
+    /// 0x00DN -> Scroll display up by N pixels
+    Scu(u8),
+    /// 0x00E0 -> Clear display
+    Cls,
+    /// 0x00EE -> Return from subroutine
+    Ret,
+    /// 0x00FB -> Scroll display right by 4 pixels
+    Scr,
+    /// 0x00FC -> Scroll display left by 4 pixels
+    Scl,
+    /// 0x00FD -> Exit the interpreter
+    Exit,
+    /// 0x00FE -> Switch to the 64x32 resolution
+    Low,
+    /// 0x00FF -> Switch to the 128x64 resolution
+    High,
+    /// 0x1NNN -> Jump to address NNN
+    Jp(Addr),
+    /// 0x2NNN -> Call subroutine at NNN
+    Call(Addr),
+    /// 0x3XNN -> Skip next instruction if VX == NN
+    SeVxByte(Reg, u8),
+    /// 0x4XNN -> Skip next instruction if VX != NN
+    SneVxByte(Reg, u8),
+    /// 0x5XY0 -> Skip next instruction if VX == VY
+    SeVxVy(Reg, Reg),
+    /// 0x5XY2 -> Store VX through VY starting at memory location I
+    LdRangeIVx(Reg, Reg),
+    /// 0x5XY3 -> Load VX through VY starting at memory location I
+    LdRangeVxI(Reg, Reg),
+    /// 0x6XNN -> VX = NN
+    LdVxByte(Reg, u8),
+    /// 0x7XNN -> VX += NN
+    AddVxByte(Reg, u8),
+    /// 0x8XY0 -> VX = VY
+    LdVxVy(Reg, Reg),
+    /// 0x8XY1 -> VX = VX | VY
+    Or(Reg, Reg),
+    /// 0x8XY2 -> VX = VX & VY
+    And(Reg, Reg),
+    /// 0x8XY3 -> VX = VX ^ VY
+    Xor(Reg, Reg),
+    /// 0x8XY4 -> VX += VY, VF = carry
+    AddVxVy(Reg, Reg),
+    /// 0x8XY5 -> VX = VX - VY, VF = not borrow
+    Sub(Reg, Reg),
+    /// 0x8XY6 -> VX >>= 1, VF = shifted out bit
+    Shr(Reg, Reg),
+    /// 0x8XY7 -> VX = VY - VX, VF = not borrow
+    Subn(Reg, Reg),
+    /// 0x8XYE -> VX <<= 1, VF = shifted out bit
+    Shl(Reg, Reg),
+    /// 0x9XY0 -> Skip next instruction if VX != VY
+    SneVxVy(Reg, Reg),
+    /// 0xANNN -> I = NNN
+    LdI(Addr),
+    /// 0xBNNN -> Jump to location V0 + NNN
+    JpV0(Addr),
+    /// 0xCXNN -> VX = random() & NN
+    Rnd(Reg, u8),
+    /// 0xDXYN -> Draw sprite at (VX, VY) with width 8 and height N
+    Drw(Reg, Reg, u8),
+    /// 0xEX9E -> Skip next instruction if key VX is pressed
+    Skp(Reg),
+    /// 0xEXA1 -> Skip next instruction if key VX is not pressed
+    Sknp(Reg),
+    /// 0xF000 NNNN -> I = NNNN, read from the next 16 bits
+    LdILong,
+    /// 0xFN01 -> Select the planes N for drawing
+    Plane(u8),
+    /// 0xF002 -> Load the audio pattern at memory location I
+    Audio,
+    /// 0xFX07 -> VX = delay timer
+    LdVxDt(Reg),
+    /// 0xFX0A -> Wait for a key press and release, then store it in VX
+    LdVxK(Reg),
+    /// 0xFX15 -> Delay timer = VX
+    LdDtVx(Reg),
+    /// 0xFX18 -> Sound timer = VX
+    LdStVx(Reg),
+    /// 0xFX1E -> I += VX
+    AddIVx(Reg),
+    /// 0xFX29 -> I = location of the sprite for the character in VX
+    LdFVx(Reg),
+    /// 0xFX30 -> I = location of the big sprite for the character in VX
+    LdHfVx(Reg),
+    /// 0xFX33 -> Store BCD repr of VX in memory locations I..I + 2
+    LdBVx(Reg),
+    /// 0xFX3A -> Set the pitch of the audio pattern to VX
+    Pitch(Reg),
+    /// 0xFX55 -> Store V0 through VX starting at memory location I
+    LdIVx(Reg),
+    /// 0xFX65 -> Load V0 through VX starting at memory location I
+    LdVxI(Reg),
+    /// 0xFX75 -> Store V0 through VX in the RPL user flags
+    LdRVx(Reg),
+    /// 0xFX85 -> Load V0 through VX from the RPL user flags
+    LdVxR(Reg),
+}
+
+/// An opcode that doesn't match any instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError(pub u16);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown opcode {:#06X}", self.0)
+    }
+}
+
+/// Decodes an opcode into an instruction
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+
+    let op = Opcode::new(opcode);
+    let x = op[Nibble::B] as Reg;
+    let y = op[Nibble::C] as Reg;
+    let n = op[Nibble::D] as u8;
+    let nn = op.get(Nibble::CD) as u8;
+    let nnn = op.get(Nibble::BCD);
+
+    let instruction = match op[Nibble::A] {
+        0x0 => match nnn {
+            0x000 => Nop,
+            0x0C0..=0x0CF => Scd(n),
+            0x0D0..=0x0DF => Scu(n),
+            0x0E0 => Cls,
+            0x0EE => Ret,
+            0x0FB => Scr,
+            0x0FC => Scl,
+            0x0FD => Exit,
+            0x0FE => Low,
+            0x0FF => High,
+            _ => Sys(nnn),
+        },
+        0x1 => Jp(nnn),
+        0x2 => Call(nnn),
+        0x3 => SeVxByte(x, nn),
+        0x4 => SneVxByte(x, nn),
+        0x5 => match n {
+            0x0 => SeVxVy(x, y),
+            0x2 => LdRangeIVx(x, y),
+            0x3 => LdRangeVxI(x, y),
+            _ => return Err(DecodeError(opcode)),
+        },
+        0x6 => LdVxByte(x, nn),
+        0x7 => AddVxByte(x, nn),
+        0x8 => match n {
+            0x0 => LdVxVy(x, y),
+            0x1 => Or(x, y),
+            0x2 => And(x, y),
+            0x3 => Xor(x, y),
+            0x4 => AddVxVy(x, y),
+            0x5 => Sub(x, y),
+            0x6 => Shr(x, y),
+            0x7 => Subn(x, y),
+            0xE => Shl(x, y),
+            _ => return Err(DecodeError(opcode)),
+        },
+        0x9 if n == 0x0 => SneVxVy(x, y),
+        0xA => LdI(nnn),
+        0xB => JpV0(nnn),
+        0xC => Rnd(x, nn),
+        0xD => Drw(x, y, n),
+        0xE => match nn {
+            0x9E => Skp(x),
+            0xA1 => Sknp(x),
+            _ => return Err(DecodeError(opcode)),
+        },
+        0xF => match nn {
+            0x00 if x == 0x0 => LdILong,
+            0x01 => Plane(x),
+            0x02 if x == 0x0 => Audio,
+            0x07 => LdVxDt(x),
+            0x0A => LdVxK(x),
+            0x15 => LdDtVx(x),
+            0x18 => LdStVx(x),
+            0x1E => AddIVx(x),
+            0x29 => LdFVx(x),
+            0x30 => LdHfVx(x),
+            0x33 => LdBVx(x),
+            0x3A => Pitch(x),
+            0x55 => LdIVx(x),
+            0x65 => LdVxI(x),
+            0x75 => LdRVx(x),
+            0x85 => LdVxR(x),
+            _ => return Err(DecodeError(opcode)),
+        },
+        _ => return Err(DecodeError(opcode)),
+    };
+
+    Ok(instruction)
+}
+
+impl Instruction {
+    /// Encodes the instruction back into its opcode
+    pub fn encode(&self) -> u16 {
+        use Instruction::*;
+
+        let x = |op: u16, x: Reg| op | (x as u16 & 0xF) << 8;
+        let xy = |op: u16, vx: Reg, vy: Reg| x(op, vx) | (vy as u16 & 0xF) << 4;
+        let xnn = |op: u16, vx: Reg, nn: u8| x(op, vx) | nn as u16;
+
+        match *self {
+            Nop => 0x0000,
+            Sys(addr) => addr & 0xFFF,
+            Scd(n) => 0x00C0 | (n as u16 & 0xF),
+            Scu(n) => 0x00D0 | (n as u16 & 0xF),
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Scr => 0x00FB,
+            Scl => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            Jp(addr) => 0x1000 | (addr & 0xFFF),
+            Call(addr) => 0x2000 | (addr & 0xFFF),
+            SeVxByte(vx, nn) => xnn(0x3000, vx, nn),
+            SneVxByte(vx, nn) => xnn(0x4000, vx, nn),
+            SeVxVy(vx, vy) => xy(0x5000, vx, vy),
+            LdRangeIVx(vx, vy) => xy(0x5002, vx, vy),
+            LdRangeVxI(vx, vy) => xy(0x5003, vx, vy),
+            LdVxByte(vx, nn) => xnn(0x6000, vx, nn),
+            AddVxByte(vx, nn) => xnn(0x7000, vx, nn),
+            LdVxVy(vx, vy) => xy(0x8000, vx, vy),
+            Or(vx, vy) => xy(0x8001, vx, vy),
+            And(vx, vy) => xy(0x8002, vx, vy),
+            Xor(vx, vy) => xy(0x8003, vx, vy),
+            AddVxVy(vx, vy) => xy(0x8004, vx, vy),
+            Sub(vx, vy) => xy(0x8005, vx, vy),
+            Shr(vx, vy) => xy(0x8006, vx, vy),
+            Subn(vx, vy) => xy(0x8007, vx, vy),
+            Shl(vx, vy) => xy(0x800E, vx, vy),
+            SneVxVy(vx, vy) => xy(0x9000, vx, vy),
+            LdI(addr) => 0xA000 | (addr & 0xFFF),
+            JpV0(addr) => 0xB000 | (addr & 0xFFF),
+            Rnd(vx, nn) => xnn(0xC000, vx, nn),
+            Drw(vx, vy, n) => xy(0xD000, vx, vy) | (n as u16 & 0xF),
+            Skp(vx) => x(0xE09E, vx),
+            Sknp(vx) => x(0xE0A1, vx),
+            LdILong => 0xF000,
+            Plane(n) => x(0xF001, n),
+            Audio => 0xF002,
+            LdVxDt(vx) => x(0xF007, vx),
+            LdVxK(vx) => x(0xF00A, vx),
+            LdDtVx(vx) => x(0xF015, vx),
+            LdStVx(vx) => x(0xF018, vx),
+            AddIVx(vx) => x(0xF01E, vx),
+            LdFVx(vx) => x(0xF029, vx),
+            LdHfVx(vx) => x(0xF030, vx),
+            LdBVx(vx) => x(0xF033, vx),
+            Pitch(vx) => x(0xF03A, vx),
+            LdIVx(vx) => x(0xF055, vx),
+            LdVxI(vx) => x(0xF065, vx),
+            LdRVx(vx) => x(0xF075, vx),
+            LdVxR(vx) => x(0xF085, vx),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Writes the canonical mnemonic of the instruction
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Nop => write!(f, "NOP"),
+            Sys(addr) => write!(f, "SYS {:#05X}", addr),
+            Scd(n) => write!(f, "SCD {:#X}", n),
+            Scu(n) => write!(f, "SCU {:#X}", n),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Scr => write!(f, "SCR"),
+            Scl => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            Jp(addr) => write!(f, "JP {:#05X}", addr),
+            Call(addr) => write!(f, "CALL {:#05X}", addr),
+            SeVxByte(x, nn) => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            SneVxByte(x, nn) => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            SeVxVy(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdRangeIVx(x, y) => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            LdRangeVxI(x, y) => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            LdVxByte(x, nn) => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            AddVxByte(x, nn) => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            LdVxVy(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddVxVy(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneVxVy(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, {:#05X}", addr),
+            JpV0(addr) => write!(f, "JP V0, {:#05X}", addr),
+            Rnd(x, nn) => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {:#X}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdILong => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {:#X}", n),
+            Audio => write!(f, "AUDIO"),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDtVx(x) => write!(f, "LD DT, V{:X}", x),
+            LdStVx(x) => write!(f, "LD ST, V{:X}", x),
+            AddIVx(x) => write!(f, "ADD I, V{:X}", x),
+            LdFVx(x) => write!(f, "LD F, V{:X}", x),
+            LdHfVx(x) => write!(f, "LD HF, V{:X}", x),
+            LdBVx(x) => write!(f, "LD B, V{:X}", x),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            LdVxR(x) => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
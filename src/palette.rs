@@ -0,0 +1,133 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use crate::display::DIMMED;
+
+/// A color as red, green and blue
+pub type Rgb = [u8; 3];
+
+/// Colors of the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    /// Colors of a pixel by its planes, where the first one is the background
+    pub colors: [Rgb; 4],
+}
+
+impl Palette {
+    /// Black pixels on white, with grays for the other XO-CHIP planes
+    pub fn classic() -> Self {
+        Self {
+            colors: [
+                [0xFF, 0xFF, 0xFF],
+                [0x00, 0x00, 0x00],
+                [0x99, 0x99, 0x99],
+                [0x4C, 0x4C, 0x4C],
+            ],
+        }
+    }
+
+    /// Green phosphor of the old monochrome monitors
+    pub fn green_phosphor() -> Self {
+        Self {
+            colors: [
+                [0x00, 0x14, 0x00],
+                [0x33, 0xFF, 0x33],
+                [0x1A, 0x99, 0x1A],
+                [0x99, 0xFF, 0x99],
+            ],
+        }
+    }
+
+    /// Amber phosphor of the old monochrome monitors
+    pub fn amber() -> Self {
+        Self {
+            colors: [
+                [0x1A, 0x0F, 0x00],
+                [0xFF, 0xB0, 0x00],
+                [0x99, 0x66, 0x00],
+                [0xFF, 0xD9, 0x80],
+            ],
+        }
+    }
+
+    /// Default colors of Octo
+    pub fn octo() -> Self {
+        Self {
+            colors: [
+                [0x99, 0x66, 0x00],
+                [0xFF, 0xCC, 0x00],
+                [0xFF, 0x66, 0x00],
+                [0x66, 0x22, 0x00],
+            ],
+        }
+    }
+
+    /// Color of a pixel by its planes, halfway to the background if dimmed
+    pub fn color(&self, pixel: u8) -> Rgb {
+        let color = self.colors[pixel as usize & 0b11];
+        if pixel & DIMMED == 0 {
+            return color;
+        }
+        let background = self.colors[0];
+        let mix =
+            |i: usize| ((color[i] as u16 + background[i] as u16) / 2) as u8;
+        [mix(0), mix(1), mix(2)]
+    }
+}
+
+/// Parses a hexadecimal color like `#FFCC00` or `ffcc00`
+fn parse_color(s: &str) -> Result<Rgb, String> {
+    let hex = s.trim().trim_start_matches('#');
+    let value = match hex.len() {
+        6 => u32::from_str_radix(hex, 16).ok(),
+        _ => None,
+    };
+    let value = value.ok_or_else(|| format!("Invalid color {}", s))?;
+    Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    /// Parses a palette by name, or 2 or 4 comma separated hexadecimal colors
+    /// starting with the background
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "classic" => return Ok(Palette::classic()),
+            "green" | "phosphor" => return Ok(Palette::green_phosphor()),
+            "amber" => return Ok(Palette::amber()),
+            "octo" => return Ok(Palette::octo()),
+            _ => {}
+        }
+
+        let colors = s
+            .split(',')
+            .map(parse_color)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| {
+                if s.contains(',') {
+                    e
+                } else {
+                    format!("Unknown palette {}", s)
+                }
+            })?;
+        match colors[..] {
+            // Pixels in the second plane are drawn with the fill color too
+            [background, fill] => Ok(Palette {
+                colors: [background, fill, fill, fill],
+            }),
+            [background, fill, fill2, blend] => Ok(Palette {
+                colors: [background, fill, fill2, blend],
+            }),
+            _ => Err(format!("Unknown palette {}", s)),
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
@@ -0,0 +1,283 @@
+use piston_window::*;
+
+use crate::display::{
+    Hotkey, Renderer, HEIGHT, HIRES_HEIGHT, HIRES_WIDTH, WIDTH,
+};
+use crate::framebuffer::FrameBuffer;
+use crate::keypad::{self, Keypad};
+use crate::palette::{Palette, Rgb};
+
+/// For every pixel, there are DEFAULT_SCALE real pixels unless another scale
+/// is given
+pub const DEFAULT_SCALE: usize = 10;
+
+/// Largest scale the window can be zoomed to
+const MAX_SCALE: usize = 40;
+
+/// Color of the scanline and grid overlays
+const OVERLAY: [f32; 4] = [0.0, 0.0, 0.0, 0.3];
+
+/// Converts a color to the format of piston
+fn color(rgb: Rgb) -> [f32; 4] {
+    let channel = |c: u8| c as f32 / 255.0;
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), 1.0]
+}
+
+/// Maps a keyboard key to the CHIP-8 keypad, the codes of the letters and
+/// digits of piston being their lowercase ASCII
+fn keypad_key(key: Key) -> Option<u8> {
+    char::from_u32(u32::from(key)).and_then(keypad::keypad_key)
+}
+
+/// A display using the piston library
+pub struct Chip8Display {
+    screen: PistonWindow,
+    /// The last frame rendered, drawn on every render event
+    frame: FrameBuffer,
+    /// Color shown by each pixel of the frame, fading out with persistence
+    shown: Vec<[f32; 4]>,
+    event: Option<Event>,
+    /// Real pixels for every pixel of the low resolution mode
+    scale: usize,
+    palette: Palette,
+    /// Share of the color a pixel keeps each frame after it's turned off
+    fade: f32,
+    scanlines: bool,
+    grid: bool,
+}
+
+impl Chip8Display {
+    pub fn new() -> Self {
+        Self::with_scale(DEFAULT_SCALE)
+    }
+
+    /// Opens a window where every pixel is scale real pixels wide
+    pub fn with_scale(scale: usize) -> Self {
+        let scale = scale.clamp(1, MAX_SCALE);
+        let palette = Palette::default();
+        Self {
+            screen: WindowSettings::new(
+                "CHIP-8 Emulator",
+                Size::from(((WIDTH * scale) as u32, (HEIGHT * scale) as u32)),
+            )
+            .resizable(true)
+            .exit_on_esc(true)
+            .build()
+            .unwrap_or_else(|e| {
+                panic!("Could not create the emulator screen {}", e);
+            }),
+            frame: FrameBuffer::new(),
+            shown: vec![color(palette.colors[0]); HIRES_WIDTH * HIRES_HEIGHT],
+            event: None,
+            scale,
+            palette,
+            fade: 0.0,
+            scanlines: false,
+            grid: false,
+        }
+    }
+
+    /// Draws with the colors of palette
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        let background = color(palette.colors[0]);
+        self.shown.iter_mut().for_each(|c| *c = background);
+        self
+    }
+
+    /// Keeps this share of the color of the pixels turned off every frame,
+    /// from 0 for none up to 0.95, which hides the flicker of XOR drawing
+    pub fn fade(mut self, fade: f32) -> Self {
+        self.fade = fade.clamp(0.0, 0.95);
+        self
+    }
+
+    /// Darkens the bottom of every row of pixels, like a CRT
+    pub fn scanlines(mut self, on: bool) -> Self {
+        self.scanlines = on;
+        self
+    }
+
+    /// Outlines every pixel
+    pub fn grid(mut self, on: bool) -> Self {
+        self.grid = on;
+        self
+    }
+
+    /// Handles the keys that change how the window looks
+    fn window_keys(&mut self) {
+        let key = match self.event.as_ref().and_then(|e| e.press_args()) {
+            Some(Button::Keyboard(key)) => key,
+            _ => return,
+        };
+        match key {
+            Key::Equals => self.zoom(1),
+            Key::Minus => self.zoom(-1),
+            Key::F6 => self.scanlines = !self.scanlines,
+            Key::F7 => self.grid = !self.grid,
+            _ => {}
+        }
+    }
+
+    /// Zooms the window in or out by one pixel per pixel
+    fn zoom(&mut self, step: isize) {
+        let scale = self.scale as isize + step;
+        self.scale = scale.clamp(1, MAX_SCALE as isize) as usize;
+        self.screen.set_size([
+            (WIDTH * self.scale) as u32,
+            (HEIGHT * self.scale) as u32,
+        ]);
+    }
+
+    /// Draws the last frame to the window on render events
+    ///
+    /// The frame is scaled to fit the window and centered in it
+    fn draw(&mut self) {
+        let &mut Self {
+            ref mut screen,
+            ref frame,
+            ref shown,
+            ref event,
+            palette,
+            scanlines,
+            grid,
+            ..
+        } = self;
+        let (e, args) = match event {
+            Some(e) => match e.render_args() {
+                Some(args) => (e, args),
+                None => return,
+            },
+            None => return,
+        };
+        let (width, height) = frame.size();
+        let (width, height) = (width as f64, height as f64);
+        let [window_width, window_height] = args.window_size;
+        let size = (window_width / width).min(window_height / height);
+        let left = (window_width - size * width) / 2.0;
+        let top = (window_height - size * height) / 2.0;
+        let background = color(palette.colors[0]);
+
+        screen.draw_2d(e, |c, g, _| {
+            clear(background, g);
+            for (y, row) in frame.rows().enumerate() {
+                for x in 0..row.len() {
+                    let shade = shown[y * HIRES_WIDTH + x];
+                    if shade != background {
+                        let (px, py) = (x as f64, y as f64);
+                        let rect =
+                            [left + px * size, top + py * size, size, size];
+                        rectangle(shade, rect, c.transform, g);
+                    }
+                }
+            }
+            // Overlays need a few real pixels per pixel to show
+            if scanlines && size >= 3.0 {
+                for y in 0..height as usize {
+                    let bottom = top + (y + 1) as f64 * size;
+                    let line = size / 3.0;
+                    let rect = [left, bottom - line, size * width, line];
+                    rectangle(OVERLAY, rect, c.transform, g);
+                }
+            }
+            if grid && size >= 3.0 {
+                let line = (size / 10.0).max(1.0);
+                for x in 0..=width as usize {
+                    let rect =
+                        [left + x as f64 * size, top, line, size * height];
+                    rectangle(OVERLAY, rect, c.transform, g);
+                }
+                for y in 0..=height as usize {
+                    let rect =
+                        [left, top + y as f64 * size, size * width, line];
+                    rectangle(OVERLAY, rect, c.transform, g);
+                }
+            }
+        });
+    }
+}
+
+impl Renderer for Chip8Display {
+    fn render(&mut self, frame: &FrameBuffer) {
+        if frame.size() != self.frame.size() {
+            // Nothing fades from the other resolution
+            let background = color(self.palette.colors[0]);
+            self.shown.iter_mut().for_each(|c| *c = background);
+        }
+        self.frame.clone_from(frame);
+
+        let background = color(self.palette.colors[0]);
+        for (y, row) in frame.rows().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                let shown = &mut self.shown[y * HIRES_WIDTH + x];
+                if pixel != 0 {
+                    *shown = color(self.palette.color(pixel));
+                } else {
+                    // Turned off pixels fade towards the background
+                    for (c, b) in shown.iter_mut().zip(background.iter()) {
+                        *c = b + (*c - b) * self.fade;
+                        if (*c - b).abs() < 1.0 / 255.0 {
+                            *c = *b;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn message(&mut self, message: &str) {
+        self.screen
+            .set_title(format!("CHIP-8 Emulator - {}", message));
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.event = self.screen.next();
+        self.window_keys();
+        self.draw();
+        self.event.is_some()
+    }
+
+    // The event loop sends update events at a steady rate, so every event
+    // is handled as soon as it arrives
+    fn paced(&self) -> bool {
+        true
+    }
+
+    fn poll_keys(&mut self, keypad: &mut dyn Keypad) {
+        if let Some(ref e) = self.event {
+            if let Some(Button::Keyboard(key)) = e.press_args() {
+                if let Some(k) = keypad_key(key) {
+                    keypad.press(k);
+                }
+            }
+            if let Some(Button::Keyboard(key)) = e.release_args() {
+                if let Some(k) = keypad_key(key) {
+                    keypad.release(k);
+                }
+            }
+        }
+    }
+
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        let event = self.event.as_ref()?;
+        if let Some(Button::Keyboard(Key::Backspace)) = event.release_args() {
+            return Some(Hotkey::Rewind(false));
+        }
+        match event.press_args()? {
+            Button::Keyboard(Key::F1) => Some(Hotkey::Slot(1)),
+            Button::Keyboard(Key::F2) => Some(Hotkey::Slot(2)),
+            Button::Keyboard(Key::F3) => Some(Hotkey::Slot(3)),
+            Button::Keyboard(Key::F4) => Some(Hotkey::Slot(4)),
+            Button::Keyboard(Key::F5) => Some(Hotkey::Save),
+            Button::Keyboard(Key::F9) => Some(Hotkey::Load),
+            Button::Keyboard(Key::Backspace) => Some(Hotkey::Rewind(true)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Chip8Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,103 @@
+use alloc::format;
+use alloc::string::String;
+use core::str::FromStr;
+
+/// How 0xFX55 and 0xFX65 change the I register after the transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged
+    Unchanged,
+    /// I is incremented by X
+    X,
+    /// I is incremented by X + 1
+    XPlusOne,
+}
+
+/// Interpreter behaviors for opcodes whose semantics changed over time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 0x8XY6 and 0x8XYE shift VY into VX instead of shifting VX in place
+    pub shift_vy: bool,
+    /// How 0xFX55 and 0xFX65 change I
+    pub index_increment: IndexIncrement,
+    /// 0xBNNN jumps to VX + NNN, X being the highest nibble of NNN, instead
+    /// of V0 + NNN
+    pub jump_vx: bool,
+    /// 0x8XY1, 0x8XY2 and 0x8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// Sprites wrap around the edges of the screen instead of being clipped
+    pub wrap: bool,
+    /// 0xDXYN waits for the next frame, drawing at most one sprite per frame
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter of the COSMAC VIP
+    pub fn cosmac_vip() -> Self {
+        Self {
+            shift_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_vx: false,
+            vf_reset: true,
+            wrap: false,
+            display_wait: true,
+        }
+    }
+
+    /// The CHIP-48 interpreter for the HP-48 calculators
+    pub fn chip48() -> Self {
+        Self {
+            shift_vy: false,
+            index_increment: IndexIncrement::X,
+            jump_vx: true,
+            vf_reset: false,
+            wrap: false,
+            display_wait: false,
+        }
+    }
+
+    /// The SUPER-CHIP 1.1 interpreter for the HP-48 calculators
+    pub fn schip() -> Self {
+        Self {
+            shift_vy: false,
+            index_increment: IndexIncrement::Unchanged,
+            jump_vx: true,
+            vf_reset: false,
+            wrap: false,
+            display_wait: false,
+        }
+    }
+
+    /// Modern interpreters such as Octo, also used by XO-CHIP
+    pub fn modern() -> Self {
+        Self {
+            shift_vy: true,
+            index_increment: IndexIncrement::XPlusOne,
+            jump_vx: false,
+            vf_reset: false,
+            wrap: true,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::modern()
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    /// Parses the name of a preset: vip, chip48, schip or modern
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vip" | "cosmac" => Ok(Self::cosmac_vip()),
+            "chip48" => Ok(Self::chip48()),
+            "schip" => Ok(Self::schip()),
+            "modern" | "octo" => Ok(Self::modern()),
+            _ => Err(format!("Unknown quirks preset {}", s)),
+        }
+    }
+}
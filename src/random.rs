@@ -0,0 +1,70 @@
+#[cfg(feature = "std")]
+use rand::prelude::{thread_rng, Rng};
+
+/// A source of random numbers for 0xCXNN
+///
+/// Its state is part of the save states, so loading one replays the same
+/// numbers
+pub trait Random {
+    /// Gets the next random byte
+    fn byte(&mut self) -> u8;
+    /// Restarts the sequence from a seed
+    fn seed(&mut self, seed: u64);
+    /// Current state of the generator
+    fn state(&self) -> u64;
+    /// Restores a state returned by state
+    fn set_state(&mut self, state: u64);
+}
+
+/// The SplitMix64 generator, seeded randomly unless a seed is given
+///
+/// Without std there is no source of entropy, so it always starts from the
+/// same seed and a frontend should give its own
+#[derive(Debug, Clone)]
+pub struct SplitMix {
+    state: u64,
+}
+
+impl SplitMix {
+    /// Creates a generator that always produces the same sequence for seed
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Gets the next 64 random bits
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl Random for SplitMix {
+    fn byte(&mut self) -> u8 {
+        (self.next() >> 56) as u8
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
+
+impl Default for SplitMix {
+    fn default() -> Self {
+        #[cfg(feature = "std")]
+        let seed = thread_rng().gen();
+        #[cfg(not(feature = "std"))]
+        let seed = 0;
+        Self::new(seed)
+    }
+}
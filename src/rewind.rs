@@ -0,0 +1,130 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::audio::Audio;
+use crate::chip8::Chip8;
+use crate::display::Renderer;
+use crate::keypad::{Keypad, KEY_COUNT};
+use crate::random::Random;
+
+/// Default memory used by the snapshots of a rewind buffer
+pub const REWIND_BYTES: usize = 16 * 1024 * 1024;
+
+/// The machine at some point in time
+struct Snapshot {
+    /// When the snapshot was taken, in the units of the caller
+    time: u64,
+    /// The machine state, as saved by save_state
+    state: Vec<u8>,
+    /// Pressed keys, one bit for each
+    keys: u16,
+}
+
+/// A ring buffer of snapshots of a Chip8 to go back in time
+///
+/// The oldest snapshots are dropped once they take more than the memory
+/// given to the buffer
+pub struct Rewind {
+    snapshots: VecDeque<Snapshot>,
+    /// Bytes taken by the snapshots
+    bytes: usize,
+    /// Most bytes the snapshots can take
+    max_bytes: usize,
+}
+
+impl Rewind {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    /// Takes a snapshot of the machine at time, replacing the snapshots
+    /// taken at or after it
+    pub fn record<D, K, A, R>(&mut self, time: u64, chip8: &Chip8<D, K, A, R>)
+    where
+        D: Renderer + Default,
+        K: Keypad + Default,
+        A: Audio + Default,
+        R: Random + Default,
+    {
+        while self.snapshots.back().is_some_and(|last| last.time >= time) {
+            self.pop_newest();
+        }
+
+        let keys = (0..KEY_COUNT as u8)
+            .filter(|&key| chip8.keypad.is_pressed(key))
+            .fold(0, |keys, key| keys | 1 << key);
+        let snapshot = Snapshot {
+            time,
+            state: chip8.save_state(),
+            keys,
+        };
+
+        self.bytes += snapshot.state.len();
+        self.snapshots.push_back(snapshot);
+        while self.bytes > self.max_bytes && self.snapshots.len() > 1 {
+            if let Some(oldest) = self.snapshots.pop_front() {
+                self.bytes -= oldest.state.len();
+            }
+        }
+    }
+
+    /// Restores the latest snapshot taken at or before time, dropping the
+    /// ones after it
+    /// Returns the time of the restored snapshot, or None if there is no
+    /// snapshot that old, in which case every snapshot is kept
+    pub fn restore<D, K, A, R>(
+        &mut self,
+        time: u64,
+        chip8: &mut Chip8<D, K, A, R>,
+    ) -> Option<u64>
+    where
+        D: Renderer + Default,
+        K: Keypad + Default,
+        A: Audio + Default,
+        R: Random + Default,
+    {
+        let index = self
+            .snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.time <= time)?;
+        while self.snapshots.len() > index + 1 {
+            self.pop_newest();
+        }
+
+        let snapshot = self.snapshots.back()?;
+        chip8.load_state(&snapshot.state).ok()?;
+        for key in 0..KEY_COUNT as u8 {
+            if snapshot.keys & 1 << key != 0 {
+                chip8.keypad.press(key);
+            } else {
+                chip8.keypad.release(key);
+            }
+        }
+        // Releasing the keys doesn't count as a key press for 0xFX0A
+        chip8.keypad.take_released();
+
+        Some(snapshot.time)
+    }
+
+    /// Drops the newest snapshot
+    fn pop_newest(&mut self) {
+        if let Some(newest) = self.snapshots.pop_back() {
+            self.bytes -= newest.state.len();
+        }
+    }
+
+    /// Time of the oldest snapshot
+    pub fn oldest(&self) -> Option<u64> {
+        self.snapshots.front().map(|snapshot| snapshot.time)
+    }
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(REWIND_BYTES)
+    }
+}
@@ -1,4 +1,7 @@
-const STACK_SIZE: usize = 16;
+use crate::error::Chip8Error;
+
+/// Maximum depth of nested calls
+pub const STACK_SIZE: usize = 16;
 
 /// The call stack of the CHIP-8
 #[derive(Debug)]
@@ -18,27 +21,41 @@ impl Stack {
         }
     }
 
-    pub fn push(&mut self, addr: u16) {
+    /// Pushes a return address, failing if the stack is full
+    pub fn push(&mut self, addr: u16) -> Result<(), Chip8Error> {
         if self.sp >= STACK_SIZE as u16 {
-            panic!("Stack Overflow");
+            return Err(Chip8Error::StackOverflow);
         }
 
         self.stack[self.sp as usize] = addr;
         self.sp += 1;
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
+    /// Pops the last return address, failing if the stack is empty
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
         if self.sp == 0 {
-            panic!("Stack Underflow");
+            return Err(Chip8Error::StackUnderflow);
         }
 
         self.sp -= 1;
 
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
+    }
+
+    /// Return addresses on the stack, the last one being the top
+    pub fn addresses(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
     }
 
-    #[allow(dead_code)]
+    #[cfg(feature = "std")]
     pub fn dump(&self) {
         println!("{:?}", self);
     }
 }
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,156 @@
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Identifies the start of a save state
+const MAGIC: &[u8; 4] = b"CH8S";
+
+/// Version of the save state format, bumped whenever its layout changes
+pub const STATE_VERSION: u8 = 2;
+
+/// Why a save state could not be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start like a save state
+    NotAState,
+    /// The save state was written by another version of the format
+    Version(u8),
+    /// The save state ends before all of its fields
+    Truncated,
+    /// A field of the save state has an impossible value
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StateError::NotAState => write!(f, "Not a save state"),
+            StateError::Version(version) => write!(
+                f,
+                "Unsupported save state version {}, expected {}",
+                version, STATE_VERSION
+            ),
+            StateError::Truncated => write!(f, "Truncated save state"),
+            StateError::Invalid(field) => {
+                write!(f, "Invalid {} in save state", field)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+/// Writes the fields of a save state, big endian
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a save state with its header
+    pub fn new() -> Self {
+        let mut data = MAGIC.to_vec();
+        data.push(STATE_VERSION);
+        Self { data }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend(&value.to_be_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(&value.to_be_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(&value.to_be_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend(bytes);
+    }
+
+    /// The finished save state
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the fields of a save state written by StateWriter
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header of a save state
+    pub fn new(data: &'a [u8]) -> Result<Self, StateError> {
+        if data.len() < MAGIC.len() + 1 || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::NotAState);
+        }
+        match data[MAGIC.len()] {
+            STATE_VERSION => Ok(Self {
+                data: &data[MAGIC.len() + 1..],
+            }),
+            version => Err(StateError::Version(version)),
+        }
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("flag")),
+        }
+    }
+
+    pub fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < n {
+            return Err(StateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// Checks that the whole save state was read
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Invalid("length"))
+        }
+    }
+}
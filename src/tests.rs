@@ -1,6 +1,28 @@
-use crate::chip8::Chip8;
-use crate::display::{Chip8Display, TextDisplay};
-use crate::opcode::{Nibble, Opcode};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::antiflicker::{AntiFlicker, FrameBlender};
+use crate::asm::{assemble, AsmError};
+use crate::audio::{Audio, NullAudio, SquareWave, SAMPLE_RATE};
+use crate::chip8::{Chip8, Platform, StepOutcome, TIMER_HZ};
+use crate::debugger::{Command, Debugger, Stop, Watch};
+use crate::disasm::{Disassembly, Line};
+use crate::display::{Renderer, TextDisplay, DIMMED};
+use crate::error::Chip8Error;
+use crate::framebuffer::FrameBuffer;
+use crate::headless::{self, parse_script, registers_json, Input, Limit};
+use crate::image::{write_pbm, write_png};
+use crate::keypad::{HexKeypad, Keypad};
+use crate::opcode::{decode, DecodeError, Instruction, Nibble, Opcode};
+use crate::palette::Palette;
+use crate::quirks::Quirks;
+use crate::random::{Random, SplitMix};
+use crate::rewind::Rewind;
+use crate::state::{StateError, STATE_VERSION};
+use crate::trace::{TraceFormat, WriteTracer};
+#[cfg(all(feature = "tui", unix))]
+use crate::tui::{frame_lines, parse_keys, TermKey};
 
 #[test]
 fn opcode_index() {
@@ -28,10 +50,113 @@ fn opcode_get() {
     assert_eq!(0xABCD, opcode.get(Nibble::ABCD));
 }
 
+#[test]
+fn opcode_decode() {
+    assert_eq!(decode(0x0000), Ok(Instruction::Nop));
+    assert_eq!(decode(0x00E0), Ok(Instruction::Cls));
+    assert_eq!(decode(0x00C4), Ok(Instruction::Scd(0x4)));
+    assert_eq!(decode(0x1234), Ok(Instruction::Jp(0x234)));
+    assert_eq!(decode(0x3A12), Ok(Instruction::SeVxByte(0xA, 0x12)));
+    assert_eq!(decode(0x5AB3), Ok(Instruction::LdRangeVxI(0xA, 0xB)));
+    assert_eq!(decode(0x8AB6), Ok(Instruction::Shr(0xA, 0xB)));
+    assert_eq!(decode(0xD125), Ok(Instruction::Drw(0x1, 0x2, 0x5)));
+    assert_eq!(decode(0xF000), Ok(Instruction::LdILong));
+    assert_eq!(decode(0xF201), Ok(Instruction::Plane(0x2)));
+    assert_eq!(decode(0xFA65), Ok(Instruction::LdVxI(0xA)));
+
+    assert_eq!(decode(0x5121), Err(DecodeError(0x5121)));
+    assert_eq!(decode(0x8128), Err(DecodeError(0x8128)));
+    assert_eq!(decode(0x9121), Err(DecodeError(0x9121)));
+    assert_eq!(decode(0xE1FF), Err(DecodeError(0xE1FF)));
+    assert_eq!(decode(0xF1FF), Err(DecodeError(0xF1FF)));
+}
+
+#[test]
+fn opcode_mnemonics() {
+    let mnemonic = |opcode| decode(opcode).unwrap().to_string();
+
+    assert_eq!(mnemonic(0x00EE), "RET");
+    assert_eq!(mnemonic(0x2ABC), "CALL 0xABC");
+    assert_eq!(mnemonic(0x6A0B), "LD VA, 0x0B");
+    assert_eq!(mnemonic(0x8124), "ADD V1, V2");
+    assert_eq!(mnemonic(0xA200), "LD I, 0x200");
+    assert_eq!(mnemonic(0xB300), "JP V0, 0x300");
+    assert_eq!(mnemonic(0xDAB0), "DRW VA, VB, 0x0");
+    assert_eq!(mnemonic(0xE5A1), "SKNP V5");
+    assert_eq!(mnemonic(0xF30A), "LD V3, K");
+    assert_eq!(mnemonic(0xF355), "LD [I], V3");
+    assert_eq!(mnemonic(0x5122), "LD [I], V1-V2");
+    assert_eq!(DecodeError(0x5121).to_string(), "Unknown opcode 0x5121");
+}
+
+#[test]
+fn opcode_encode() {
+    for opcode in 0..=0xFFFF {
+        if let Ok(instruction) = decode(opcode) {
+            assert_eq!(instruction.encode(), opcode);
+        }
+    }
+}
+
+#[test]
+fn disasm_control_flow() {
+    let rom = vec![
+        0xA2, 0x0A, // 0x200: LD I, 0x20A
+        0x22, 0x08, // 0x202: CALL 0x208
+        0x30, 0x00, // 0x204: SE V0, 0x00
+        0x12, 0x04, // 0x206: JP 0x204
+        0x00, 0xEE, // 0x208: RET
+        0xF0, 0x90, // 0x20A: sprite data
+    ];
+    let disassembly = Disassembly::new(&rom);
+    let lines = disassembly.lines();
+
+    assert_eq!(
+        lines[0],
+        (0x200, Line::Code(0xA20A, Instruction::LdI(0x20A)))
+    );
+    assert_eq!(lines[4], (0x208, Line::Code(0x00EE, Instruction::Ret)));
+    assert_eq!(lines[5], (0x20A, Line::Data(0xF0)));
+    assert_eq!(lines[6], (0x20B, Line::Data(0x90)));
+    assert_eq!(disassembly.label(0x204), Some("L204".to_string()));
+    assert_eq!(disassembly.label(0x20A), Some("D20A".to_string()));
+    assert_eq!(disassembly.label(0x202), None);
+
+    let text = disassembly.to_string();
+    assert!(text.contains("L208:\n    RET"));
+    assert!(text.contains("    LD I, D20A"));
+    assert!(text.contains("    db 0b11110000           ; 0x20A: ####...."));
+}
+
+#[test]
+fn disasm_skip_over_long() {
+    let rom = vec![
+        0x30, 0x00, // 0x200: SE V0, 0x00
+        0xF0, 0x00, // 0x202: LD I, LONG
+        0x03, 0x00, // 0x204: 0x300
+        0x00, 0xFD, // 0x206: EXIT
+    ];
+    let lines = Disassembly::new(&rom).lines().to_vec();
+
+    assert_eq!(lines[2], (0x204, Line::Long(0x300)));
+    assert_eq!(lines[3], (0x206, Line::Code(0x00FD, Instruction::Exit)));
+
+    // An 0xF000 at the end of memory has no address after it
+    let mut rom = [0x60, 0x00].repeat(0x7EFF);
+    rom.extend_from_slice(&[0xF0, 0x00]);
+    let lines = Disassembly::new(&rom).lines().to_vec();
+    assert_eq!(
+        lines.last(),
+        Some(&(0xFFFE, Line::Code(0xF000, Instruction::LdILong)))
+    );
+}
+
 #[test]
 fn chip_test_instruction_0x5() {
     // 0x5XY0 -> SE VX, VY
-    let mut chip = Chip8::<TextDisplay>::new().debug().no_display();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
 
     // 0x200 LD V0, 0x0
     // 0x202 LD V1, 0x0
@@ -49,23 +174,23 @@ fn chip_test_instruction_0x5() {
         0x61, 0x0A, 0x50, 0x10, 0x60, 0x0A, 0x50, 0x10, 0x50, 0x0B,
     ];
 
-    chip.load_rom(&data);
+    chip.load_rom(&data).unwrap();
 
-    chip.cycle(); // LD V0, 0x0
-    chip.cycle(); // LD V1, 0x0
+    chip.cycle().unwrap(); // LD V0, 0x0
+    chip.cycle().unwrap(); // LD V1, 0x0
     assert_eq!(chip.cpu.v[0x0], 0x0);
     assert_eq!(chip.cpu.v[0x1], 0x0);
-    chip.cycle(); // Skip
-    chip.cycle(); // V0 = 0x0C
+    chip.cycle().unwrap(); // Skip
+    chip.cycle().unwrap(); // V0 = 0x0C
     assert_eq!(chip.cpu.v[0x0], 0x0C);
-    chip.cycle(); // No Skip
-    chip.cycle(); // V1 = 0xA
+    chip.cycle().unwrap(); // No Skip
+    chip.cycle().unwrap(); // V1 = 0xA
     assert_eq!(chip.cpu.v[0x1], 0x0A);
-    chip.cycle(); // No Skip
-    chip.cycle(); // V0 = 0xA
+    chip.cycle().unwrap(); // No Skip
+    chip.cycle().unwrap(); // V0 = 0xA
     assert_eq!(chip.cpu.v[0x0], 0x0A);
-    chip.cycle(); // Skip
-    chip.cycle(); // NOP
+    chip.cycle().unwrap(); // Skip
+    chip.cycle().unwrap(); // NOP
     assert_eq!(chip.cpu.v[0x0], 0x0A);
     assert_eq!(chip.cpu.v[0x1], 0x0A);
 }
@@ -73,16 +198,18 @@ fn chip_test_instruction_0x5() {
 #[test]
 fn chip_test_instruction_0x6() {
     // 0x6XNN -> LD VX, NN
-    let mut chip = Chip8::<TextDisplay>::new().debug().no_display();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
 
     // 0x200: LD V0, 0x0A
     // 0x202: LD V1, 0x0B
     let data: Vec<u8> = vec![0x60, 0x0A, 0x61, 0x0B];
 
-    chip.load_rom(&data);
+    chip.load_rom(&data).unwrap();
 
-    chip.cycle();
-    chip.cycle();
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
 
     assert_eq!(chip.cpu.v[0x0], 0x0A);
     assert_eq!(chip.cpu.v[0x1], 0x0B);
@@ -91,7 +218,9 @@ fn chip_test_instruction_0x6() {
 #[test]
 fn chip_test_instruction_0x7() {
     // 0x6XNN -> LD VX, NN
-    let mut chip = Chip8::<TextDisplay>::new().debug().no_display();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
 
     // 0x200:  LD V0, 0x0
     // 0x202:  LD V1, 0x0
@@ -103,31 +232,1453 @@ fn chip_test_instruction_0x7() {
         0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0x70, 0x01,
     ];
 
-    chip.load_rom(&data);
+    chip.load_rom(&data).unwrap();
 
-    chip.cycle(); // LD V0, 0x0
-    chip.cycle(); // LD V1, 0x0
+    chip.cycle().unwrap(); // LD V0, 0x0
+    chip.cycle().unwrap(); // LD V1, 0x0
 
     for i in 0..9 {
         assert_eq!(chip.cpu.v[0x0], i);
-        chip.cycle();
+        chip.cycle().unwrap();
     }
 }
 
+/// A Random that always returns the same byte
+#[derive(Default)]
+struct FixedRandom(u8);
+
+impl Random for FixedRandom {
+    fn byte(&mut self) -> u8 {
+        self.0
+    }
+
+    fn seed(&mut self, seed: u64) {
+        self.0 = seed as u8;
+    }
+
+    fn state(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.0 = state as u8;
+    }
+}
+
+#[test]
+fn chip_test_instruction_0xc() {
+    // 0xCXNN -> RND VX, NN
+    let rom = vec![
+        0xC1, 0x0F, // 0x200: RND V1, 0x0F
+        0xC2, 0xF0, // 0x202: RND V2, 0xF0
+    ];
+
+    let mut chip =
+        Chip8::<TextDisplay, HexKeypad, NullAudio, FixedRandom>::new(
+            Quirks::default(),
+        )
+        .seed(0xAB)
+        .no_display();
+    chip.load_rom(&rom).unwrap();
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.v[0x1], 0x0B);
+    assert_eq!(chip.cpu.v[0x2], 0xA0);
+
+    // The same seed always gives the same numbers
+    let mut rng = SplitMix::new(42);
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .seed(42)
+        .no_display();
+    chip.load_rom(&rom).unwrap();
+    chip.cycle().unwrap();
+    let state = chip.save_state();
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.v[0x1], rng.byte() & 0x0F);
+    assert_eq!(chip.cpu.v[0x2], rng.byte() & 0xF0);
+
+    // Save states restore the generator
+    let v2 = chip.cpu.v[0x2];
+    chip.load_state(&state).unwrap();
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.v[0x2], v2);
+}
+
+#[test]
+fn chip_test_instruction_0xe() {
+    // 0xEX9E -> SKP VX
+    // 0xEXA1 -> SKNP VX
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: LD V0, 0x5
+    // 0x202: SKP V0
+    // 0x204: SKP V0
+    // 0x206: NOP
+    // 0x208: SKNP V0
+    // 0x20A: SKNP V0
+    let data: Vec<u8> = vec![
+        0x60, 0x05, 0xE0, 0x9E, 0xE0, 0x9E, 0x00, 0x00, 0xE0, 0xA1, 0xE0, 0xA1,
+    ];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap(); // LD V0, 0x5
+    chip.cycle().unwrap(); // No Skip
+    assert_eq!(chip.cpu.pc, 0x204);
+    chip.keypad.press(0x5);
+    chip.cycle().unwrap(); // Skip
+    assert_eq!(chip.cpu.pc, 0x208);
+    chip.cycle().unwrap(); // No Skip
+    assert_eq!(chip.cpu.pc, 0x20A);
+    chip.keypad.release(0x5);
+    chip.cycle().unwrap(); // Skip
+    assert_eq!(chip.cpu.pc, 0x20E);
+}
+
+#[test]
+fn chip_test_instruction_0xf_wait_key() {
+    // 0xFX0A -> LD VX, K
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: LD V3, K
+    // 0x202: LD V0, 0x1
+    let data: Vec<u8> = vec![0xF3, 0x0A, 0x60, 0x01];
+
+    chip.load_rom(&data).unwrap();
+
+    // A key released before the wait does not count
+    chip.keypad.press(0x2);
+    chip.keypad.release(0x2);
+
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.pc, 0x200);
+
+    // Holding a key is not enough, it has to be released
+    chip.keypad.press(0xA);
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.pc, 0x200);
+
+    chip.keypad.release(0xA);
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.pc, 0x202);
+    assert_eq!(chip.cpu.v[0x3], 0xA);
+
+    chip.cycle().unwrap(); // LD V0, 0x1
+    assert_eq!(chip.cpu.v[0x0], 0x1);
+}
+
+#[test]
+fn chip_test_frame_timers() {
+    // Timers tick once per frame, not once per instruction
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .speed(120)
+        .no_display();
+
+    // 0x200: LD V0, 0x3
+    // 0x202: LD DT, V0
+    // 0x204: LD V1, DT
+    // 0x206: LD V2, DT
+    let data: Vec<u8> = vec![0x60, 0x03, 0xF0, 0x15, 0xF1, 0x07, 0xF2, 0x07];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.frame().unwrap(); // 2 instructions, DT = 3 - 1
+    chip.frame().unwrap(); // 2 instructions, DT = 2 - 1
+    assert_eq!(chip.cpu.v[0x1], 0x2);
+    assert_eq!(chip.cpu.v[0x2], 0x2);
+}
+
+#[test]
+fn chip_test_frame_speed() {
+    // 90 instructions per second alternate between 1 and 2 per frame
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .speed(90)
+        .no_display();
+
+    chip.load_rom(&[0x00; 16]).unwrap();
+
+    chip.frame().unwrap();
+    assert_eq!(chip.cpu.pc, 0x202);
+    chip.frame().unwrap();
+    assert_eq!(chip.cpu.pc, 0x206);
+    chip.frame().unwrap();
+    assert_eq!(chip.cpu.pc, 0x208);
+}
+
+#[test]
+fn chip_test_sound_timer() {
+    // 0xFX18 -> LD ST, VX
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .speed(120)
+        .no_display();
+
+    // 0x200: LD V0, 0x3
+    // 0x202: LD ST, V0
+    let data: Vec<u8> = vec![0x60, 0x03, 0xF0, 0x18];
+
+    chip.load_rom(&data).unwrap();
+
+    for _ in 0..6 {
+        chip.frame().unwrap();
+    }
+
+    assert_eq!(chip.audio.frames, 6);
+    assert_eq!(chip.audio.beeps, vec![(0, 3)]);
+}
+
+#[test]
+fn audio_square_wave() {
+    let mut audio = SquareWave::new();
+    let frame = (SAMPLE_RATE / TIMER_HZ) as usize;
+
+    audio.frame();
+    audio.beep(true);
+    audio.frame();
+    audio.beep(false);
+    audio.frame();
+
+    let samples = audio.samples();
+    assert_eq!(samples.len(), frame * 3);
+    assert!(samples[..frame].iter().all(|s| *s == 0));
+    assert!(samples[frame..frame * 2].iter().any(|s| *s > 0));
+    assert!(samples[frame..frame * 2].iter().any(|s| *s < 0));
+    assert!(samples[frame * 2..].iter().all(|s| *s == 0));
+
+    let mut wav = Vec::new();
+    audio.write_wav(&mut wav).unwrap();
+    assert_eq!(&wav[0..4], b"RIFF");
+    assert_eq!(&wav[8..12], b"WAVE");
+    assert_eq!(wav.len(), 44 + frame * 3 * 2);
+}
+
+#[test]
+fn chip_test_instruction_0xd() {
+    // 0xDXYN -> DRW VX, VY, N
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: LD V0, 0x2
+    // 0x202: LD V1, 0x3
+    // 0x204: LD I, 0x000
+    // 0x206: DRW V0, V1, 0x5
+    // 0x208: DRW V0, V1, 0x5
+    let data: Vec<u8> =
+        vec![0x60, 0x02, 0x61, 0x03, 0xA0, 0x00, 0xD0, 0x15, 0xD0, 0x15];
+
+    chip.load_rom(&data).unwrap();
+
+    for _ in 0..4 {
+        chip.cycle().unwrap();
+    }
+
+    // The "0" sprite is a 4x5 box
+    assert_eq!(chip.cpu.v[0xF], 0);
+    assert_eq!(chip.frame.get_pixel(2, 3), 1);
+    assert_eq!(chip.frame.get_pixel(5, 3), 1);
+    assert_eq!(chip.frame.get_pixel(3, 4), 0);
+    assert_eq!(chip.frame.get_pixel(2, 7), 1);
+    assert_eq!(chip.frame.get_pixel(6, 3), 0);
+
+    // Drawing it again erases it and sets the collision flag
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.v[0xF], 1);
+    assert_eq!(chip.frame.get_pixel(2, 3), 0);
+    assert_eq!(chip.frame.get_pixel(5, 3), 0);
+}
+
+#[test]
+fn framebuffer_draw() {
+    let mut frame = FrameBuffer::new();
+    let sprite = [0b1100_0000, 0b1000_0000];
+
+    // Drawing twice XORs the pixels back off and reports the collision
+    assert!(!frame.draw(2, 3, 8, 2, &sprite, false));
+    assert_eq!(frame.get_pixel(3, 3), 1);
+    assert_eq!(frame.get_pixel(3, 4), 0);
+    assert!(frame.draw(2, 3, 8, 2, &sprite, false));
+    assert!(frame.rows().all(|row| row.iter().all(|&p| p == 0)));
+
+    // The start wraps around, then the sprite is clipped or wraps
+    frame.draw(63 + 64, 31, 8, 2, &sprite, false);
+    assert_eq!(frame.get_pixel(63, 31), 1);
+    assert_eq!(frame.get_pixel(0, 31), 0);
+    assert_eq!(frame.get_pixel(63, 0), 0);
+    frame.clear();
+    frame.draw(63, 31, 8, 2, &sprite, true);
+    assert_eq!(frame.get_pixel(0, 31), 1);
+    assert_eq!(frame.get_pixel(63, 0), 1);
+
+    frame.set_hires(true);
+    assert_eq!(frame.size(), (128, 64));
+    assert_eq!(frame.rows().count(), 64);
+    assert!(frame.rows().all(|row| row.len() == 128));
+}
+
+/// Keeps every frame it renders
+#[derive(Default)]
+struct FrameRecorder(Vec<FrameBuffer>);
+
+impl Renderer for FrameRecorder {
+    fn render(&mut self, frame: &FrameBuffer) {
+        self.0.push(frame.clone());
+    }
+}
+
+#[test]
+fn chip_test_renderer() {
+    let rom = assemble("LD F, V0\nDRW V0, V0, 5\nloop: JP loop").unwrap();
+    let mut chip = Chip8::<FrameRecorder>::new(Quirks::default()).speed(60);
+    chip.load_rom(&rom).unwrap();
+
+    // Each frame is rendered once, as drawn by the program so far
+    chip.frame().unwrap();
+    chip.frame().unwrap();
+    assert_eq!(chip.display.0.len(), 2);
+    assert_eq!(chip.display.0[0], FrameBuffer::new());
+    assert_eq!(chip.display.0[1], chip.frame);
+    assert_eq!(chip.frame.get_pixel(0, 0), 1);
+
+    chip.set_display(false);
+    chip.frame().unwrap();
+    assert_eq!(chip.display.0.len(), 2);
+}
+
+#[cfg(all(feature = "tui", unix))]
+#[test]
+fn tui_parse_keys() {
+    use crate::display::Hotkey;
+
+    assert_eq!(
+        parse_keys(b"1Qv\x1BOQ\x1B[15~\x1B[20~\x1B[A\x1Bq\x03\x1B"),
+        (
+            vec![
+                TermKey::Keypad(0x1),
+                TermKey::Keypad(0x4),
+                TermKey::Keypad(0xF),
+                TermKey::Hotkey(Hotkey::Slot(2)),
+                TermKey::Hotkey(Hotkey::Save),
+                TermKey::Hotkey(Hotkey::Load),
+                TermKey::Quit,
+                TermKey::Quit,
+            ],
+            23
+        )
+    );
+
+    // A sequence split between reads is kept for the next one
+    assert_eq!(parse_keys(b"1\x1B[1"), (vec![TermKey::Keypad(0x1)], 1));
+    assert_eq!(
+        parse_keys(b"\x1B[15~"),
+        (vec![TermKey::Hotkey(Hotkey::Save)], 5)
+    );
+}
+
+#[cfg(all(feature = "tui", unix))]
+#[test]
+fn tui_frame_lines() {
+    let mut frame = FrameBuffer::new();
+    frame.set_pixel(0, 1, 1);
+    let lines = frame_lines(&frame, &["PC 0x200".to_string()]);
+
+    // Two rows per line, with the colors only sent when they change
+    assert_eq!(lines.len(), 16);
+    assert!(lines[0].starts_with(
+        "\x1B[38;5;16m\x1B[48;5;231m\u{2580}\x1B[38;5;16m\x1B[48;5;16m"
+    ));
+    assert!(lines[0].ends_with("\u{2580}\x1B[0m  PC 0x200\x1B[K"));
+    assert_eq!(lines[1].matches('\u{2580}').count(), 64);
+    assert!(lines[1].ends_with("\x1B[0m  \x1B[K"));
+
+    frame.set_hires(true);
+    assert_eq!(frame_lines(&frame, &[]).len(), 32);
+}
+
+#[test]
+fn antiflicker_blend() {
+    assert_eq!("OR:3".parse(), Ok(AntiFlicker::Or(3)));
+    assert_eq!("blend".parse(), Ok(AntiFlicker::Blend(2)));
+    assert!("or:0".parse::<AntiFlicker>().is_err());
+    assert!("xor".parse::<AntiFlicker>().is_err());
+
+    // A sprite erased and drawn again one pixel to the right
+    let rom = assemble(
+        "LD I, dot\nDRW V0, V0, 1\nADD V1, 1\nCLS\nDRW V1, V0, 1\ndot: db 0x80",
+    )
+    .unwrap();
+    let run = |mode| {
+        let display = FrameBlender::new(FrameRecorder::default(), mode);
+        let mut chip = Chip8::<FrameBlender<FrameRecorder>>::with_display(
+            Quirks::default(),
+            display,
+        )
+        .speed(180);
+        chip.load_rom(&rom).unwrap();
+        chip.frame().unwrap();
+        chip.frame().unwrap();
+
+        // The emulated frame is left as is
+        assert_eq!(chip.frame.get_pixel(0, 0), 0);
+        assert_eq!(chip.frame.get_pixel(1, 0), 1);
+        let last = chip.display.inner.0.last().unwrap().clone();
+        (last.get_pixel(0, 0), last.get_pixel(1, 0))
+    };
+    assert_eq!(run(AntiFlicker::Or(1)), (0, 1));
+    assert_eq!(run(AntiFlicker::Or(2)), (1, 1));
+    assert_eq!(run(AntiFlicker::Blend(2)), (1 | DIMMED, 1));
+
+    // Dimmed pixels are halfway to the background, even with 2 colors
+    let palette: Palette = "000000,FFFFFF".parse().unwrap();
+    assert_eq!(palette.color(1), [0xFF, 0xFF, 0xFF]);
+    assert_eq!(palette.color(1 | DIMMED), [0x7F, 0x7F, 0x7F]);
+}
+
+#[test]
+fn chip_test_schip_display() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::SuperChip)
+        .debug()
+        .no_display();
+
+    // 0x200: HIGH
+    // 0x202: LD V0, 0x3A
+    // 0x204: LD I, 0x20C
+    // 0x206: DRW V0, V0, 0x0
+    // 0x208: SCD 0x2
+    // 0x20A: SCL
+    // 0x20C: 16x16 sprite, top row filled
+    let mut data: Vec<u8> = vec![
+        0x00, 0xFF, 0x60, 0x3A, 0xA2, 0x0C, 0xD0, 0x00, 0x00, 0xC2, 0x00, 0xFC,
+        0xFF, 0xFF,
+    ];
+    data.extend_from_slice(&[0x00; 30]);
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap(); // HIGH
+    assert_eq!(chip.frame.size(), (128, 64));
+    chip.cycle().unwrap(); // LD V0, 0x3A
+    chip.cycle().unwrap(); // LD I, 0x20C
+    chip.cycle().unwrap(); // DRW V0, V0, 0x0
+    assert_eq!(chip.frame.get_pixel(0x3A, 0x3A), 1);
+    assert_eq!(chip.frame.get_pixel(0x3A + 15, 0x3A), 1);
+    assert_eq!(chip.frame.get_pixel(0x3A + 16, 0x3A), 0);
+    assert_eq!(chip.frame.get_pixel(0x3A, 0x3B), 0);
+
+    chip.cycle().unwrap(); // SCD 0x2
+    assert_eq!(chip.frame.get_pixel(0x3A, 0x3A), 0);
+    assert_eq!(chip.frame.get_pixel(0x3A, 0x3C), 1);
+
+    chip.cycle().unwrap(); // SCL
+    assert_eq!(chip.frame.get_pixel(0x3A - 4, 0x3C), 1);
+    assert_eq!(chip.frame.get_pixel(0x3A + 12, 0x3C), 0);
+}
+
+#[test]
+fn chip_test_schip_flags_and_exit() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::SuperChip)
+        .debug()
+        .no_display();
+
+    // 0x200: LD V0, 0x1
+    // 0x202: LD V1, 0x2
+    // 0x204: LD R, V1
+    // 0x206: LD V0, 0x0
+    // 0x208: LD V1, 0x0
+    // 0x20A: LD V1, R
+    // 0x20C: LD HF, V0
+    // 0x20E: EXIT
+    let data: Vec<u8> = vec![
+        0x60, 0x01, 0x61, 0x02, 0xF1, 0x75, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x85,
+        0xF0, 0x30, 0x00, 0xFD,
+    ];
+
+    chip.load_rom(&data).unwrap();
+
+    for _ in 0..6 {
+        chip.cycle().unwrap();
+    }
+    assert_eq!(chip.cpu.v[0x0], 0x1);
+    assert_eq!(chip.cpu.v[0x1], 0x2);
+
+    chip.cycle().unwrap(); // LD HF, V0
+    assert_eq!(chip.cpu.i, 0x50 + 10);
+
+    assert!(!chip.exited());
+    chip.cycle().unwrap(); // EXIT
+    assert!(chip.exited());
+}
+
+#[test]
+fn chip_test_schip_disabled() {
+    // SUPER-CHIP instructions are ignored by the original CHIP-8
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: HIGH
+    // 0x202: EXIT
+    let data: Vec<u8> = vec![0x00, 0xFF, 0x00, 0xFD];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
+    assert_eq!(chip.frame.size(), (64, 32));
+    assert!(!chip.exited());
+    assert_eq!(chip.cpu.pc, 0x204);
+}
+
+#[test]
+fn chip_test_xochip_memory() {
+    // Only the XO-CHIP has 64 KiB of memory
+    let chip = Chip8::<TextDisplay>::new(Quirks::default());
+    assert_eq!(chip.mem.mem.len(), 0x1000);
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::XoChip)
+        .debug()
+        .no_display();
+    assert_eq!(chip.mem.mem.len(), 0x10000);
+
+    // 0x200: LD V0, 0x1
+    // 0x202: LD V1, 0x2
+    // 0x204: LD V2, 0x3
+    // 0x206: SE V0, 0x1
+    // 0x208: LD I, 0xFFF0
+    // 0x20C: LD I, 0xFFF0
+    // 0x210: LD [I], V2-V0
+    // 0x212: LD V0-V2, [I]
+    let data: Vec<u8> = vec![
+        0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x30, 0x01, 0xF0, 0x00, 0xFF, 0xF0,
+        0xF0, 0x00, 0xFF, 0xF0, 0x52, 0x02, 0x50, 0x23,
+    ];
+
+    chip.load_rom(&data).unwrap();
+
+    for _ in 0..3 {
+        chip.cycle().unwrap();
+    }
+
+    // Skipping over a 4 bytes instruction
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.pc, 0x20C);
+
+    chip.cycle().unwrap(); // LD I, 0xFFF0
+    assert_eq!(chip.cpu.i, 0xFFF0);
+    assert_eq!(chip.cpu.pc, 0x210);
+
+    chip.cycle().unwrap(); // LD [I], V2-V0
+    assert_eq!(chip.mem.get_slice(0xFFF0, 3).unwrap(), &[0x3, 0x2, 0x1]);
+    assert_eq!(chip.cpu.i, 0xFFF0);
+
+    chip.cycle().unwrap(); // LD V0-V2, [I]
+    assert_eq!(chip.cpu.v[..3], [0x3, 0x2, 0x1]);
+
+    // I goes past 0xFFF without touching VF
+    let rom = assemble("ADD I, V0").unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::XoChip)
+        .no_display();
+    chip.load_rom(&rom).unwrap();
+    chip.cpu.i = 0xFFF;
+    chip.cpu.v[0x0] = 0x1;
+    chip.cpu.v[0xF] = 0x7;
+    chip.cycle().unwrap();
+    assert_eq!((chip.cpu.i, chip.cpu.v[0xF]), (0x1000, 0x7));
+}
+
+#[test]
+fn chip_test_xochip_planes() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::XoChip)
+        .debug()
+        .no_display();
+
+    // 0x200: PLANE 0x3
+    // 0x202: LD I, 0x208
+    // 0x204: DRW V0, V0, 0x1
+    // 0x206: PLANE 0x2
+    // 0x208: sprite for plane 1
+    // 0x209: sprite for plane 2
+    let data: Vec<u8> =
+        vec![0xF3, 0x01, 0xA2, 0x08, 0xD0, 0x01, 0xF2, 0x01, 0xC0, 0x60];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap(); // PLANE 0x3
+    chip.cycle().unwrap(); // LD I, 0x208
+    chip.cycle().unwrap(); // DRW V0, V0, 0x1
+    assert_eq!(chip.frame.get_pixel(0, 0), 0b01);
+    assert_eq!(chip.frame.get_pixel(1, 0), 0b11);
+    assert_eq!(chip.frame.get_pixel(2, 0), 0b10);
+    assert_eq!(chip.frame.get_pixel(3, 0), 0b00);
+
+    // Only the second plane is cleared
+    chip.cycle().unwrap(); // PLANE 0x2
+    chip.frame.clear();
+    assert_eq!(chip.frame.get_pixel(0, 0), 0b01);
+    assert_eq!(chip.frame.get_pixel(1, 0), 0b01);
+    assert_eq!(chip.frame.get_pixel(2, 0), 0b00);
+}
+
+#[test]
+fn chip_test_xochip_audio() {
+    let mut chip =
+        Chip8::<TextDisplay, HexKeypad, SquareWave>::new(Quirks::default())
+            .platform(Platform::XoChip)
+            .speed(300)
+            .no_display();
+
+    // 0x200: LD I, 0x20C
+    // 0x202: AUDIO
+    // 0x204: LD V0, 0xFF
+    // 0x206: PITCH V0
+    // 0x208: LD ST, V0
+    // 0x20A: JP 0x20A
+    // 0x20C: audio pattern, high for the first half
+    let mut data: Vec<u8> = vec![
+        0xA2, 0x0C, 0xF0, 0x02, 0x60, 0xFF, 0xF0, 0x3A, 0xF0, 0x18, 0x12, 0x0A,
+    ];
+    data.extend_from_slice(&[0xFF; 8]);
+    data.extend_from_slice(&[0x00; 8]);
+
+    chip.load_rom(&data).unwrap();
+
+    chip.frame().unwrap();
+
+    let samples = chip.audio.samples();
+    assert_eq!(samples.len(), (SAMPLE_RATE / TIMER_HZ) as usize);
+    assert!(samples[0] > 0);
+    assert!(samples.iter().any(|s| *s < 0));
+}
+
+#[test]
+fn chip_test_quirks_shift_and_logic() {
+    // 0x200: LD V0, 0x3
+    // 0x202: LD V1, 0x4
+    // 0x204: LD VF, 0x1
+    // 0x206: OR V1, V0
+    // 0x208: SHR V0, V1
+    let data: Vec<u8> =
+        vec![0x60, 0x03, 0x61, 0x04, 0x6F, 0x01, 0x81, 0x01, 0x80, 0x16];
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::schip()).no_display();
+    chip.load_rom(&data).unwrap();
+    for _ in 0..5 {
+        chip.cycle().unwrap();
+    }
+    assert_eq!(chip.cpu.v[0x1], 0x7);
+    assert_eq!(chip.cpu.v[0x0], 0x1);
+    assert_eq!(chip.cpu.v[0xF], 0x1);
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::cosmac_vip()).no_display();
+    chip.load_rom(&data).unwrap();
+    for _ in 0..4 {
+        chip.cycle().unwrap();
+    }
+    assert_eq!(chip.cpu.v[0xF], 0x0);
+    chip.cycle().unwrap();
+    assert_eq!(chip.cpu.v[0x0], 0x3);
+    assert_eq!(chip.cpu.v[0xF], 0x1);
+}
+
+#[test]
+fn chip_test_quirks_jump_and_index() {
+    // 0x200: LD V0, 0x2
+    // 0x202: LD V2, 0x4
+    // 0x204: LD I, 0x300
+    // 0x206: LD [I], V2
+    // 0x208: JP V0, 0x210
+    let data: Vec<u8> =
+        vec![0x60, 0x02, 0x62, 0x04, 0xA3, 0x00, 0xF2, 0x55, 0xB2, 0x10];
+
+    let expected = [
+        (Quirks::cosmac_vip(), 0x303, 0x212),
+        (Quirks::chip48(), 0x302, 0x214),
+        (Quirks::schip(), 0x300, 0x214),
+        (Quirks::modern(), 0x303, 0x212),
+    ];
+    for (quirks, i, pc) in expected.iter() {
+        let mut chip = Chip8::<TextDisplay>::new(*quirks).no_display();
+        chip.load_rom(&data).unwrap();
+        for _ in 0..5 {
+            chip.cycle().unwrap();
+        }
+        assert_eq!(chip.cpu.i, *i);
+        assert_eq!(chip.cpu.pc, *pc);
+    }
+}
+
+#[test]
+fn chip_test_quirks_wrap() {
+    // 0x200: LD V0, 0x3E
+    // 0x202: LD V1, 0x1F
+    // 0x204: LD I, 0x20A
+    // 0x206: DRW V0, V1, 0x2
+    // 0x208: NOP
+    // 0x20A: 2 rows of 4 pixels
+    let data: Vec<u8> = vec![
+        0x60, 0x3E, 0x61, 0x1F, 0xA2, 0x0A, 0xD0, 0x12, 0x00, 0x00, 0xF0, 0xF0,
+    ];
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::modern()).no_display();
+    chip.load_rom(&data).unwrap();
+    for _ in 0..4 {
+        chip.cycle().unwrap();
+    }
+    assert_eq!(chip.frame.get_pixel(63, 31), 1);
+    assert_eq!(chip.frame.get_pixel(0, 31), 1);
+    assert_eq!(chip.frame.get_pixel(1, 0), 1);
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::schip()).no_display();
+    chip.load_rom(&data).unwrap();
+    for _ in 0..4 {
+        chip.cycle().unwrap();
+    }
+    assert_eq!(chip.frame.get_pixel(63, 31), 1);
+    assert_eq!(chip.frame.get_pixel(0, 31), 0);
+    assert_eq!(chip.frame.get_pixel(1, 0), 0);
+}
+
+#[test]
+fn chip_test_quirks_display_wait() {
+    // 0x200: DRW V0, V0, 0x1
+    // 0x202: DRW V0, V0, 0x1
+    let data: Vec<u8> = vec![0xD0, 0x01, 0xD0, 0x01];
+
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::cosmac_vip())
+        .speed(600)
+        .no_display();
+    chip.load_rom(&data).unwrap();
+
+    chip.frame().unwrap();
+    assert_eq!(chip.cpu.pc, 0x202);
+    chip.frame().unwrap();
+    assert_eq!(chip.cpu.pc, 0x204);
+
+    // The debugger ends the frame on a draw too, ticking the timers
+    // 0x200: LD V0, 0x5
+    // 0x202: LD DT, V0
+    // 0x204: DRW V0, V0, 0x1
+    // 0x206: LD V1, DT
+    let data: Vec<u8> = vec![0x60, 0x05, 0xF0, 0x15, 0xD0, 0x01, 0xF1, 0x07];
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::cosmac_vip())
+        .speed(600)
+        .no_display();
+    chip.load_rom(&data).unwrap();
+    let mut debugger = Debugger::new(chip);
+    for _ in 0..4 {
+        assert_eq!(debugger.step(), Stop::Done);
+    }
+    assert_eq!(debugger.chip8.cpu.v[0x1], 4);
+}
+
+#[test]
+fn chip_test_call_ret() {
+    // 0x2NNN -> CALL NNN
+    // 0x00EE -> RET
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: CALL 0x206
+    // 0x202: LD V0, 0x1
+    // 0x204: JP 0x204
+    // 0x206: LD V1, 0x2
+    // 0x208: RET
+    let data: Vec<u8> =
+        vec![0x22, 0x06, 0x60, 0x01, 0x12, 0x04, 0x61, 0x02, 0x00, 0xEE];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap(); // CALL 0x206
+    assert_eq!(chip.cpu.pc, 0x206);
+    chip.cycle().unwrap(); // LD V1, 0x2
+    chip.cycle().unwrap(); // RET
+    assert_eq!(chip.cpu.pc, 0x202);
+    chip.cycle().unwrap(); // LD V0, 0x1
+    assert_eq!(chip.cpu.v[0x0], 0x1);
+    assert_eq!(chip.cpu.v[0x1], 0x2);
+}
+
+#[test]
+fn chip_test_instruction_0x8_flags() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .debug()
+        .no_display();
+
+    // 0x200: LD V0, 0x10
+    // 0x202: LD V1, 0xF0
+    // 0x204: ADD V1, V0
+    // 0x206: SUB V1, V0
+    // 0x208: SUBN V1, V0
+    // 0x20A: LD V2, 0x0
+    // 0x20C: SUB V0, VF
+    let data: Vec<u8> = vec![
+        0x60, 0x10, 0x61, 0xF0, 0x81, 0x04, 0x81, 0x05, 0x81, 0x07, 0x62, 0x00,
+        0x80, 0xF5,
+    ];
+
+    chip.load_rom(&data).unwrap();
+
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
+    chip.cycle().unwrap(); // ADD V1, V0
+    assert_eq!(chip.cpu.v[0x1], 0x00);
+    assert_eq!(chip.cpu.v[0xF], 0x1);
+    chip.cycle().unwrap(); // SUB V1, V0
+    assert_eq!(chip.cpu.v[0x1], 0xF0);
+    assert_eq!(chip.cpu.v[0xF], 0x0);
+    chip.cycle().unwrap(); // SUBN V1, V0
+    assert_eq!(chip.cpu.v[0x1], 0x20);
+    assert_eq!(chip.cpu.v[0xF], 0x0);
+    chip.cycle().unwrap();
+    chip.cycle().unwrap(); // SUB V0, VF
+    assert_eq!(chip.cpu.v[0x0], 0x10);
+    assert_eq!(chip.cpu.v[0xF], 0x1);
+}
+
+#[test]
+fn asm_program() {
+    let source = "
+        SPEED = 2           ; a constant
+        start:
+            LD I, sprite
+            LD V0, SPEED + 1
+            ADD V0, -1
+            LD [I], V1-V2
+            JP start
+        sprite:
+            db \"#..#\", 0x0F
+            dw sprite
+    ";
+
+    assert_eq!(
+        assemble(source),
+        Ok(vec![
+            0xA2, 0x0A, // 0x200: LD I, 0x20A
+            0x60, 0x03, // 0x202: LD V0, 0x03
+            0x70, 0xFF, // 0x204: ADD V0, 0xFF
+            0x51, 0x22, // 0x206: LD [I], V1-V2
+            0x12, 0x00, // 0x208: JP 0x200
+            0x90, 0x0F, // 0x20A: sprite
+            0x02, 0x0A, // 0x20C: 0x20A
+        ])
+    );
+}
+
+#[test]
+fn asm_errors() {
+    let error = |line, message: &str| {
+        Err(AsmError {
+            line,
+            message: message.to_string(),
+        })
+    };
+
+    assert_eq!(
+        assemble("CLS\nJP nowhere"),
+        error(2, "Unknown symbol nowhere")
+    );
+    assert_eq!(assemble("FOO V1"), error(1, "Unknown instruction FOO"));
+    assert_eq!(assemble("LD K, V1"), error(1, "Invalid operands for LD"));
+    assert_eq!(assemble("a:\na:"), error(2, "a is already defined"));
+    assert_eq!(
+        assemble("DRW V0, V1, 16"),
+        error(1, "16 doesn't fit in 4 bits")
+    );
+    assert_eq!(
+        assemble("CLS\n\nLD I, 0x1000"),
+        error(3, "Address 0x1000 out of range")
+    );
+}
+
+#[test]
+fn asm_disasm_roundtrip() {
+    let rom = include_bytes!("../data/TETRIS");
+    let source = Disassembly::new(rom).to_string();
+
+    assert_eq!(assemble(&source).as_deref(), Ok(&rom[..]));
+}
+
+#[test]
+fn debugger_step_and_step_over() {
+    let rom = assemble(
+        "
+            CALL sub
+            CALL sub
+        end:
+            JP end
+        sub:
+            ADD V1, 1
+            RET
+        ",
+    )
+    .unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&rom).unwrap();
+    let mut debugger = Debugger::new(chip);
+
+    // Stepping over runs the whole subroutine
+    assert_eq!(debugger.step_over(), Stop::Done);
+    assert_eq!(debugger.chip8.cpu.pc, 0x202);
+    assert_eq!(debugger.chip8.cpu.v[0x1], 1);
+    assert!(debugger.chip8.stack.addresses().is_empty());
+
+    // Stepping enters it
+    assert_eq!(debugger.step(), Stop::Done);
+    assert_eq!(debugger.chip8.cpu.pc, 0x206);
+    assert_eq!(debugger.chip8.stack.addresses(), &[0x204]);
+
+    // A CALL at the end of memory returns to 0x0
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::XoChip)
+        .no_display();
+    chip.load_rom(&[0x00, 0xEE]).unwrap();
+    chip.mem
+        .get_slice_mut(0xFFFE, 2)
+        .unwrap()
+        .copy_from_slice(&[0x22, 0x00]);
+    chip.cpu.pc = 0xFFFE;
+    let mut debugger = Debugger::new(chip);
+    assert_eq!(debugger.step_over(), Stop::Done);
+    assert_eq!(debugger.chip8.cpu.pc, 0x0);
+}
+
+#[test]
+fn debugger_breakpoints_and_watchpoints() {
+    let rom = assemble(
+        "
+        loop:
+            ADD V0, 1
+            SE V0, 3
+            JP loop
+            LD I, 0x300
+            LD [I], V0
+        end:
+            JP end
+        ",
+    )
+    .unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&rom).unwrap();
+    let mut debugger = Debugger::new(chip);
+    let mut output = Vec::new();
+
+    debugger.add_breakpoint(0x206);
+    assert_eq!(debugger.resume(|_| false), Stop::Breakpoint(0x206));
+    assert_eq!(debugger.chip8.cpu.v[0x0], 3);
+
+    debugger.add_watch(Watch::Memory(0x300)).unwrap();
+    assert_eq!(
+        debugger.resume(|_| false),
+        Stop::Watchpoint(Watch::Memory(0x300), 0, 3)
+    );
+    assert_eq!(debugger.chip8.cpu.pc, 0x20A);
+
+    assert_eq!("watch va".parse(), Ok(Command::Watch(Watch::Register(0xA))));
+    assert_eq!("x 0x300 2".parse(), Ok(Command::Memory(0x300, 2)));
+    assert!("break".parse::<Command>().is_err());
+
+    debugger
+        .execute(Command::Memory(0x300, 2), &mut output)
+        .unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), "0x0300: 03 00\n");
+
+    // An infinite loop stops at the limit, showing where it is
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&[0x12, 0x00]).unwrap();
+    let mut debugger = Debugger::new(chip).limit(100);
+    let mut output = Vec::new();
+    debugger.execute(Command::Continue, &mut output).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Stopped after 100 instructions\n=> 0x200: 1200  JP 0x200\n"
+    );
+}
+
+#[test]
+fn chip_test_save_state() {
+    let rom = assemble(
+        "
+            HIGH
+            CALL sub
+        sub:
+            LD V0, 0x20
+            LD DT, V0
+            LD F, V0
+            DRW V0, V0, 5
+        end:
+            JP end
+        ",
+    )
+    .unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .platform(Platform::SuperChip)
+        .no_display();
+    chip.load_rom(&rom).unwrap();
+    for _ in 0..6 {
+        chip.cycle().unwrap();
+    }
+    let state = chip.save_state();
+
+    let mut restored =
+        Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(restored.cpu.pc, 0x20C);
+    assert_eq!(restored.stack.addresses(), &[0x204]);
+    assert_eq!(restored.delay_timer(), 0x20);
+    assert_eq!(restored.frame.size(), (128, 64));
+    assert_eq!(restored.frame.get_pixel(0x20, 0x20), 0x1);
+    assert_eq!(restored.save_state(), state);
+}
+
+#[test]
+fn chip_test_save_state_errors() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    let mut state = chip.save_state();
+
+    // An XO-CHIP state needs 64 KiB of memory
+    state[5] = Platform::XoChip as u8;
+    assert_eq!(
+        chip.load_state(&state),
+        Err(StateError::Invalid("memory size"))
+    );
+    state[5] = Platform::Chip8 as u8;
+
+    assert_eq!(chip.load_state(b"ROM"), Err(StateError::NotAState));
+    assert_eq!(
+        chip.load_state(&state[..state.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    state[4] = STATE_VERSION + 1;
+    assert_eq!(
+        chip.load_state(&state),
+        Err(StateError::Version(STATE_VERSION + 1))
+    );
+}
+
+#[test]
+fn rewind_restore() {
+    let rom = assemble(
+        "
+        loop:
+            RND V0, 0xFF
+            ADD V1, V0
+            JP loop
+        ",
+    )
+    .unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&rom).unwrap();
+    let mut rewind = Rewind::default();
+
+    for frame in 0..10 {
+        rewind.record(frame, &chip);
+        chip.frame().unwrap();
+    }
+    let state = chip.save_state();
+
+    // Going back restores the random numbers too, so the frames replay
+    assert_eq!(rewind.restore(4, &mut chip), Some(4));
+    for _ in 4..10 {
+        chip.frame().unwrap();
+    }
+    assert_eq!(chip.save_state(), state);
+
+    // Snapshots after the restored one are gone
+    assert_eq!(rewind.restore(9, &mut chip), Some(4));
+    assert_eq!(rewind.oldest(), Some(0));
+}
+
+#[test]
+fn rewind_bounded_memory() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    let size = chip.save_state().len();
+    let mut rewind = Rewind::new(size * 3);
+
+    for frame in 0..10 {
+        rewind.record(frame, &chip);
+        chip.frame().unwrap();
+    }
+    assert_eq!(rewind.oldest(), Some(7));
+    assert_eq!(rewind.restore(6, &mut chip), None);
+
+    // Failing to go back that far keeps the history
+    assert_eq!(rewind.oldest(), Some(7));
+    assert_eq!(rewind.restore(8, &mut chip), Some(8));
+    assert_eq!(rewind.oldest(), Some(7));
+}
+
+#[test]
+fn debugger_reverse_step() {
+    let rom = assemble(
+        "
+        loop:
+            RND V0, 0xFF
+            ADD V1, V0
+            SKNP V2
+            ADD V3, 1
+            JP loop
+        ",
+    )
+    .unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&rom).unwrap();
+    let mut debugger = Debugger::new(chip);
+    let mut output = Vec::new();
+
+    let mut history = Vec::new();
+    for i in 0..60 {
+        if i == 5 {
+            debugger.execute(Command::Press(0x0), &mut output).unwrap();
+        }
+        history.push(debugger.chip8.save_state());
+        debugger.step();
+    }
+    history.push(debugger.chip8.save_state());
+
+    debugger.reverse_step(1).unwrap();
+    assert_eq!(debugger.chip8.save_state(), history[59]);
+    debugger.reverse_step(45).unwrap();
+    assert_eq!(debugger.chip8.save_state(), history[14]);
+
+    // Replaying goes through the same random numbers and key presses
+    for _ in 14..60 {
+        debugger.step();
+    }
+    assert_eq!(debugger.chip8.save_state(), history[60]);
+
+    // Going back too far stops at the start
+    debugger.reverse_step(100).unwrap();
+    assert_eq!(debugger.chip8.save_state(), history[0]);
+}
+
+#[test]
+fn headless_parse_script() {
+    let inputs = parse_script(
+        "
+        # Jump, then let go
+        3 release a
+        1 press A # both cases
+        ",
+    )
+    .unwrap();
+    assert_eq!(
+        inputs,
+        [
+            Input {
+                frame: 1,
+                key: 0xA,
+                pressed: true,
+            },
+            Input {
+                frame: 3,
+                key: 0xA,
+                pressed: false,
+            },
+        ]
+    );
+
+    assert_eq!(
+        parse_script("1 press").unwrap_err(),
+        "line 1: Expected <frame> press|release <key>"
+    );
+    assert_eq!(
+        parse_script("\n1 hold 2").unwrap_err(),
+        "line 2: Expected press or release"
+    );
+    assert_eq!(
+        parse_script("1 press 10").unwrap_err(),
+        "line 1: Invalid key"
+    );
+    assert_eq!(
+        parse_script("x press 1").unwrap_err(),
+        "line 1: Invalid frame"
+    );
+}
+
+#[test]
+fn headless_run_limits() {
+    let rom = assemble(
+        "
+        loop:
+            SKNP V2
+            ADD V3, 1
+            ADD V0, 1
+            JP loop
+        ",
+    )
+    .unwrap();
+    let new_chip = || {
+        let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+            .speed(600)
+            .no_display();
+        chip.load_rom(&rom).unwrap();
+        chip
+    };
+
+    // The limit can stop in the middle of a frame
+    let mut chip = new_chip();
+    let progress = headless::run(&mut chip, Limit::Cycles(15), &[]);
+    assert_eq!((progress.cycles, progress.frames), (15, 1));
+
+    let mut chip = new_chip();
+    let inputs = parse_script("2 press 0\n3 release 0").unwrap();
+    let progress = headless::run(&mut chip, Limit::Frames(4), &inputs);
+    assert_eq!((progress.cycles, progress.frames), (40, 4));
+    assert!(!chip.keypad.is_pressed(0));
+    // V3 was only increased while the key was held, during the third frame
+    assert!(chip.cpu.v[0x3] > 0 && chip.cpu.v[0x3] < 10);
+
+    let json = registers_json(&chip, progress);
+    assert!(json.contains(&format!("\"v\": [{}, ", chip.cpu.v[0])));
+    assert!(json.contains("\"stack\": []"));
+    assert!(json.contains("\"cycles\": 40,\n  \"frames\": 4\n}"));
+}
+
+#[test]
+fn headless_images() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.frame.set_pixel(0, 0, 1);
+    chip.frame.set_pixel(63, 31, 1);
+
+    let mut pbm = Vec::new();
+    write_pbm(&chip.frame, &mut pbm).unwrap();
+    let pbm = String::from_utf8(pbm).unwrap();
+    let lines: Vec<&str> = pbm.lines().collect();
+    assert_eq!(lines[..2], ["P1", "64 32"]);
+    assert_eq!(lines.len(), 2 + 32);
+    assert!(lines[2].starts_with("1 0 0"));
+    assert!(lines[33].ends_with("0 0 1"));
+
+    let mut png = Vec::new();
+    write_png(&chip.frame, &mut png).unwrap();
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1A\n");
+    assert_eq!(&png[12..16], b"IHDR");
+    assert_eq!(&png[16..24], &[0, 0, 0, 64, 0, 0, 0, 32]);
+    // IEND and its well known checksum end the file
+    assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xAE\x42\x60\x82");
+}
+
+/// A writer whose output can be read while the emulator owns it
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn chip_test_options() {
+    assert_eq!("vip".parse(), Ok(Quirks::cosmac_vip()));
+    assert_eq!("SCHIP".parse(), Ok(Quirks::schip()));
+    assert_eq!(
+        "foo".parse::<Quirks>(),
+        Err("Unknown quirks preset foo".to_string())
+    );
+    assert_eq!("xochip".parse(), Ok(Platform::XoChip));
+    assert_eq!(
+        "nes".parse::<Platform>(),
+        Err("Unknown platform nes".to_string())
+    );
+    assert_eq!(Platform::SuperChip.to_string(), "schip");
+    assert_eq!(Platform::Chip8.max_rom_size(), 0xE00);
+    assert_eq!(Platform::XoChip.max_rom_size(), 0xFE00);
+}
+
+#[test]
+fn palette_parse() {
+    assert_eq!("Amber".parse(), Ok(Palette::amber()));
+    assert_eq!(
+        "octo".parse::<Palette>().unwrap().color(1),
+        [0xFF, 0xCC, 0x00]
+    );
+    assert_eq!(
+        "#000000,33ff33".parse(),
+        Ok(Palette {
+            colors: [
+                [0, 0, 0],
+                [0x33, 0xFF, 0x33],
+                [0x33, 0xFF, 0x33],
+                [0x33, 0xFF, 0x33]
+            ],
+        })
+    );
+    let palette: Palette = "#000000,#111111,#222222,#333333".parse().unwrap();
+    assert_eq!(palette.color(0b11), [0x33, 0x33, 0x33]);
+    assert_eq!(
+        "sepia".parse::<Palette>(),
+        Err("Unknown palette sepia".to_string())
+    );
+    assert_eq!(
+        "#000000,#12345".parse::<Palette>(),
+        Err("Invalid color #12345".to_string())
+    );
+    assert!("#000000,#111111,#222222".parse::<Palette>().is_err());
+}
+
+#[test]
+fn chip_test_trace() {
+    let rom = assemble("LD V0, 1\nLD V1, 2").unwrap();
+    let trace = SharedBuffer::default();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+        .trace(trace.clone())
+        .no_display();
+    chip.load_rom(&rom).unwrap();
+    chip.cycle().unwrap();
+    chip.cycle().unwrap();
+
+    let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+    assert_eq!(trace, "0x200:\tLD V0, 0x01\n0x202:\tLD V1, 0x02\n");
+}
+
+#[test]
+fn chip_test_trace_formats() {
+    let rom = assemble("LD V0, 0x2A\nLD I, 0x300\nADD V0, V0").unwrap();
+    let run = |format| {
+        let trace = SharedBuffer::default();
+        let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+            .tracer(WriteTracer::with_format(trace.clone(), format))
+            .no_display();
+        chip.load_rom(&rom).unwrap();
+        for _ in 0..3 {
+            chip.cycle().unwrap();
+        }
+        let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+        trace
+    };
+
+    assert_eq!(
+        run(TraceFormat::Csv),
+        "pc,opcode,mnemonic,changes,i\n\
+         0x200,602A,\"LD V0, 0x2A\",V0=00>2A,0x000\n\
+         0x202,A300,\"LD I, 0x300\",,0x300\n\
+         0x204,8004,\"ADD V0, V0\",V0=2A>54,0x300\n"
+    );
+    assert_eq!(
+        run(TraceFormat::JsonLines).lines().next(),
+        Some(
+            "{\"pc\": 512, \"opcode\": 24618, \"mnemonic\": \"LD V0, 0x2A\", \
+             \"changes\": {\"V0\": [0, 42]}, \"i\": 0}"
+        )
+    );
+    assert_eq!("JSONL".parse(), Ok(TraceFormat::JsonLines));
+    assert!("xml".parse::<TraceFormat>().is_err());
+
+    // Waiting for a key is traced when the key sets VX
+    let rom = assemble("LD V3, K").unwrap();
+    let run = |format| {
+        let trace = SharedBuffer::default();
+        let mut chip = Chip8::<TextDisplay>::new(Quirks::default())
+            .tracer(WriteTracer::with_format(trace.clone(), format))
+            .no_display();
+        chip.load_rom(&rom).unwrap();
+        chip.cycle().unwrap();
+        chip.keypad.press(0x7);
+        chip.cycle().unwrap();
+        chip.keypad.release(0x7);
+        chip.cycle().unwrap();
+        let trace = String::from_utf8(trace.0.borrow().clone()).unwrap();
+        trace
+    };
+    assert_eq!(run(TraceFormat::Text), "0x200:\tLD V3, K\n");
+    assert_eq!(
+        run(TraceFormat::Csv),
+        "pc,opcode,mnemonic,changes,i\n0x200,F30A,\"LD V3, K\",V3=00>07,0x000\n"
+    );
+    assert_eq!(
+        run(TraceFormat::JsonLines),
+        "{\"pc\": 512, \"opcode\": 62218, \"mnemonic\": \"LD V3, K\", \
+         \"changes\": {\"V3\": [0, 7]}, \"i\": 0}\n"
+    );
+}
+
+#[test]
+fn chip_test_errors() {
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    assert_eq!(
+        chip.load_rom(&[0; 0xE01]),
+        Err(Chip8Error::RomTooLarge {
+            size: 0xE01,
+            max: 0xE00
+        })
+    );
+
+    // Errors leave PC on the faulting instruction
+    let rom = assemble("RET\nloop: CALL loop").unwrap();
+    chip.load_rom(&rom).unwrap();
+    assert_eq!(chip.cycle(), Err(Chip8Error::StackUnderflow));
+    assert_eq!(chip.cpu.pc, 0x200);
+    chip.cpu.pc = 0x202;
+    for _ in 0..16 {
+        assert_eq!(
+            chip.cycle(),
+            Ok(StepOutcome::Executed(Instruction::Call(0x202)))
+        );
+    }
+    assert_eq!(chip.cycle(), Err(Chip8Error::StackOverflow));
+    assert_eq!((chip.cpu.pc, chip.stack.addresses().len()), (0x202, 16));
+
+    // Nothing is written when the registers don't all fit in memory
+    let rom =
+        assemble("LD I, 0xFFE\nLD [I], V2\nLD I, 0xFFF\nADD I, V0").unwrap();
+    let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).no_display();
+    chip.load_rom(&rom).unwrap();
+    chip.cpu.v[..3].copy_from_slice(&[0xFF, 0x2, 0x3]);
+    chip.cycle().unwrap();
+    assert_eq!(chip.cycle(), Err(Chip8Error::OutOfMemory(0x1000)));
+    assert_eq!(chip.mem.get_slice(0xFFE, 2), Ok(&[0, 0][..]));
+    assert_eq!(chip.opcode(), Ok(0xF255));
+
+    // I wraps around instead of overflowing
+    chip.cpu.pc = 0x204;
+    chip.cycle().unwrap();
+    chip.cpu.i = 0xFFFF;
+    chip.cycle().unwrap();
+    assert_eq!((chip.cpu.i, chip.cpu.v[0xF]), (0xFE, 0));
+
+    // Jumping out of memory fails on the next fetch
+    let mut debugger = Debugger::new(chip);
+    debugger.chip8.cpu.pc = 0xFFF;
+    assert_eq!(
+        debugger.step(),
+        Stop::Crashed(Chip8Error::OutOfMemory(0x1000))
+    );
+    let progress = headless::run(&mut debugger.chip8, Limit::Frames(1), &[]);
+    assert_eq!(progress.error, Some(Chip8Error::OutOfMemory(0x1000)));
+    assert!(registers_json(&debugger.chip8, progress)
+        .contains("\"error\": \"Address 0x1000 out of memory\""));
+}
+
 // #[test]
 // fn chip_test_display() {
 //     // A visual display test
 
-//     let mut chip = Chip8::<TextDisplay>::new().debug();
+//     let mut chip = Chip8::<TextDisplay>::new(Quirks::default()).debug();
 
 //     // 0x200  LD I, 0x000
 //     // 0x202 DRW V0, V1, 0x5
 //     let data: Vec<u8> = vec![0xA0, 0x00, 0xD0, 0x15];
 
-//     chip.load_rom(&data);
+//     chip.load_rom(&data).unwrap();
 
-//     chip.cycle();
-//     chip.cycle();
+//     chip.cycle().unwrap();
+//     chip.cycle().unwrap();
 
 //     std::thread::sleep(std::time::Duration::from_secs(2));
 // }
@@ -0,0 +1,144 @@
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "std")]
+use std::str::FromStr;
+
+use crate::opcode::Instruction;
+
+/// An executed instruction and what it did to the registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    /// Address of the instruction
+    pub pc: u16,
+    /// Raw opcode
+    pub opcode: u16,
+    pub instruction: Instruction,
+    /// V0 to VF before the instruction
+    pub before: [u8; 16],
+    /// V0 to VF after the instruction
+    pub after: [u8; 16],
+    /// I after the instruction
+    pub i: u16,
+}
+
+impl Step {
+    /// The (register, before, after) of the registers that changed
+    pub fn changes(&self) -> impl Iterator<Item = (usize, u8, u8)> + '_ {
+        (0..16)
+            .filter(move |&x| self.before[x] != self.after[x])
+            .map(move |x| (x, self.before[x], self.after[x]))
+    }
+}
+
+/// Receives every instruction executed by Chip8
+pub trait Tracer {
+    /// Called after an instruction was executed
+    fn trace(&mut self, step: &Step);
+}
+
+/// How WriteTracer writes each instruction
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// `0x200:\tLD V0, 0x01`, like the debug output
+    Text,
+    /// `pc,opcode,mnemonic,changes,i` with a header
+    Csv,
+    /// One JSON object per line
+    JsonLines,
+}
+
+#[cfg(feature = "std")]
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(TraceFormat::Text),
+            "csv" => Ok(TraceFormat::Csv),
+            "jsonl" | "json" => Ok(TraceFormat::JsonLines),
+            _ => Err(format!("Unknown trace format {}", s)),
+        }
+    }
+}
+
+/// Writes each instruction as a line of text
+#[cfg(feature = "std")]
+pub struct WriteTracer<W: Write> {
+    out: W,
+    format: TraceFormat,
+    /// If the CSV header was written
+    started: bool,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> WriteTracer<W> {
+    pub fn new(out: W) -> Self {
+        Self::with_format(out, TraceFormat::Text)
+    }
+
+    pub fn with_format(out: W, format: TraceFormat) -> Self {
+        Self {
+            out,
+            format,
+            started: false,
+        }
+    }
+
+    fn write(&mut self, step: &Step) -> std::io::Result<()> {
+        match self.format {
+            TraceFormat::Text => {
+                writeln!(self.out, "{:#X}:\t{}", step.pc, step.instruction)
+            }
+            TraceFormat::Csv => {
+                if !self.started {
+                    writeln!(self.out, "pc,opcode,mnemonic,changes,i")?;
+                }
+                let changes: Vec<String> = step
+                    .changes()
+                    .map(|(x, old, new)| {
+                        format!("V{:X}={:02X}>{:02X}", x, old, new)
+                    })
+                    .collect();
+                // Mnemonics have commas but no quotes
+                writeln!(
+                    self.out,
+                    "{:#05X},{:04X},\"{}\",{},{:#05X}",
+                    step.pc,
+                    step.opcode,
+                    step.instruction,
+                    changes.join(" "),
+                    step.i
+                )
+            }
+            TraceFormat::JsonLines => {
+                let changes: Vec<String> = step
+                    .changes()
+                    .map(|(x, old, new)| {
+                        format!("\"V{:X}\": [{}, {}]", x, old, new)
+                    })
+                    .collect();
+                // Mnemonics have no quotes or backslashes to escape
+                writeln!(
+                    self.out,
+                    "{{\"pc\": {}, \"opcode\": {}, \"mnemonic\": \"{}\", \
+                     \"changes\": {{{}}}, \"i\": {}}}",
+                    step.pc,
+                    step.opcode,
+                    step.instruction,
+                    changes.join(", "),
+                    step.i
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write> Tracer for WriteTracer<W> {
+    fn trace(&mut self, step: &Step) {
+        // Tracing is best effort and never stops the emulator
+        let _ = self.write(step);
+        self.started = true;
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::{self, Write};
+use std::mem::{self, MaybeUninit};
+use std::time::{Duration, Instant};
+
+use crate::display::{Hotkey, Renderer, Status, DIMMED};
+use crate::framebuffer::FrameBuffer;
+use crate::keypad::{keypad_key, Keypad, KEY_COUNT};
+
+/// Terminals only send key presses, so a key stays held for KEY_HOLD after
+/// its last press, which the auto repeat of the terminal keeps renewing
+pub const KEY_HOLD: Duration = Duration::from_millis(200);
+
+/// 256 color palette entries of a pixel by its planes
+const COLORS: [u8; 4] = [16, 231, 248, 242];
+
+/// 256 color palette entries of a dimmed pixel by its planes
+const DIMMED_COLORS: [u8; 4] = [16, 244, 240, 238];
+
+/// 256 color palette entry of a pixel
+fn color(pixel: u8) -> u8 {
+    if pixel & DIMMED != 0 {
+        DIMMED_COLORS[pixel as usize & 0b11]
+    } else {
+        COLORS[pixel as usize & 0b11]
+    }
+}
+
+/// A key read from the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TermKey {
+    Keypad(u8),
+    Hotkey(Hotkey),
+    /// Escape or Ctrl-C
+    Quit,
+}
+
+/// Longest unfinished escape sequence kept for the next read, longer ones
+/// are dropped
+const MAX_SEQUENCE: usize = 16;
+
+/// Parses the bytes read from a terminal in raw mode, skipping unknown keys,
+/// along with how many bytes were parsed, the rest being the start of an
+/// escape sequence split between reads
+pub fn parse_keys(bytes: &[u8]) -> (Vec<TermKey>, usize) {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let rest = &bytes[i..];
+        let (key, len) = match rest {
+            // F1 to F4 are sent as SS3 P to S
+            [0x1B, b'O', c @ b'P'..=b'S', ..] => {
+                (Some(TermKey::Hotkey(Hotkey::Slot(c - b'P' + 1))), 3)
+            }
+            [0x1B, b'[', b'1', b'5', b'~', ..] => {
+                (Some(TermKey::Hotkey(Hotkey::Save)), 5)
+            }
+            [0x1B, b'[', b'2', b'0', b'~', ..] => {
+                (Some(TermKey::Hotkey(Hotkey::Load)), 5)
+            }
+            // Other escape sequences end with a letter or a tilde
+            [0x1B, b'[', ..] | [0x1B, b'O', ..] => {
+                let end = rest[2..]
+                    .iter()
+                    .position(|c| c.is_ascii_alphabetic() || *c == b'~');
+                match end {
+                    Some(n) => (None, n + 3),
+                    None if rest.len() < MAX_SEQUENCE => break,
+                    None => (None, rest.len()),
+                }
+            }
+            // Escape is only a key when nothing follows it, otherwise it
+            // starts an Alt combination
+            [0x1B] | [0x03, ..] => (Some(TermKey::Quit), 1),
+            [0x1B, 0x1B, ..] => (None, 1),
+            [0x1B, _, ..] => (None, 2),
+            [c, ..] => (keypad_key(*c as char).map(TermKey::Keypad), 1),
+            [] => unreachable!(),
+        };
+        keys.extend(key);
+        i += len;
+    }
+    (keys, i)
+}
+
+/// Draws a frame with half blocks, each character showing two pixels, with
+/// the lines of the side panel on its right
+pub fn frame_lines(frame: &FrameBuffer, panel: &[String]) -> Vec<String> {
+    let rows: Vec<&[u8]> = frame.rows().collect();
+    let mut lines = Vec::new();
+    for (n, pair) in rows.chunks(2).enumerate() {
+        let mut line = String::new();
+        let mut colors = None;
+        for (x, &top) in pair[0].iter().enumerate() {
+            let bottom = pair.get(1).map_or(0, |row| row[x]);
+            let cell = (color(top), color(bottom));
+            // Colors are only sent when they change
+            if colors != Some(cell) {
+                line += &format!("\x1B[38;5;{}m\x1B[48;5;{}m", cell.0, cell.1);
+                colors = Some(cell);
+            }
+            line.push('\u{2580}');
+        }
+        let text = panel.get(n).map_or("", String::as_str);
+        line += &format!("\x1B[0m  {}\x1B[K", text);
+        lines.push(line);
+    }
+    lines
+}
+
+/// Lines of the side panel
+fn panel(status: &Status) -> Vec<String> {
+    let mut lines = vec![
+        format!("PC {:#05X}   I {:#05X}", status.pc, status.i),
+        String::new(),
+    ];
+    for (n, v) in status.v.chunks(4).enumerate() {
+        let regs: Vec<String> = v
+            .iter()
+            .enumerate()
+            .map(|(x, v)| format!("V{:X} {:02X}", n * 4 + x, v))
+            .collect();
+        lines.push(regs.join("  "));
+    }
+    lines.push(String::new());
+    lines.push(format!(
+        "DT {:3}   ST {:3}",
+        status.delay_timer, status.sound_timer
+    ));
+    lines.push(String::new());
+    lines.push("Esc quits, F1-F4 slot, F5 save, F9 load".to_string());
+    lines
+}
+
+/// The terminal settings before raw mode, restored on drop
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    /// Puts stdin in raw mode, where reads return right away
+    fn enable() -> io::Result<Self> {
+        let mut termios = MaybeUninit::uninit();
+        // Safety: tcgetattr fills termios when it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+
+        let mut raw = original;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        // Safety: raw is a valid termios copied from the current one
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) }
+            != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self { original })
+    }
+
+    /// Reads the bytes available on stdin without waiting
+    fn read(&self, buffer: &mut [u8]) -> usize {
+        // Safety: buffer is valid for buffer.len() bytes
+        let n = unsafe {
+            libc::read(
+                libc::STDIN_FILENO,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+            )
+        };
+        n.max(0) as usize
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // Safety: original was filled by tcgetattr
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+/// A display drawn in the terminal, which also works over SSH
+pub struct TerminalDisplay {
+    raw: RawMode,
+    status: Option<Status>,
+    /// The result of the last hotkey, shown below the panel
+    message: Option<String>,
+    /// The lines on screen, only the ones that change are drawn again
+    lines: Vec<String>,
+    /// When each key was last pressed, if it's held
+    held: [Option<Instant>; KEY_COUNT],
+    hotkeys: Vec<Hotkey>,
+    /// Start of an escape sequence whose end wasn't read yet
+    pending: Vec<u8>,
+    open: bool,
+}
+
+impl TerminalDisplay {
+    /// Switches the terminal to raw mode and to its alternate screen
+    pub fn open() -> io::Result<Self> {
+        let raw = RawMode::enable()?;
+        let mut out = io::stdout();
+        write!(out, "\x1B[?1049h\x1B[?25l\x1B[2J")?;
+        out.flush()?;
+        Ok(Self {
+            raw,
+            status: None,
+            message: None,
+            lines: Vec::new(),
+            held: [None; KEY_COUNT],
+            hotkeys: Vec::new(),
+            pending: Vec::new(),
+            open: true,
+        })
+    }
+
+    /// Reads the keys pressed since the last call
+    fn read_keys(&mut self) {
+        let mut buffer = [0; 64];
+        let mut bytes = mem::take(&mut self.pending);
+        loop {
+            let n = self.raw.read(&mut buffer);
+            if n == 0 {
+                break;
+            }
+            bytes.extend_from_slice(&buffer[..n]);
+        }
+
+        let (keys, parsed) = parse_keys(&bytes);
+        self.pending = bytes.split_off(parsed);
+        let now = Instant::now();
+        for key in keys {
+            match key {
+                TermKey::Keypad(k) => self.held[k as usize] = Some(now),
+                TermKey::Hotkey(hotkey) => self.hotkeys.push(hotkey),
+                TermKey::Quit => self.open = false,
+            }
+        }
+    }
+}
+
+impl Renderer for TerminalDisplay {
+    fn render(&mut self, frame: &FrameBuffer) {
+        let mut panel = self.status.as_ref().map(panel).unwrap_or_default();
+        if let Some(message) = &self.message {
+            panel.push(String::new());
+            panel.push(message.clone());
+        }
+        let lines = frame_lines(frame, &panel);
+
+        // Lines are drawn in place, as clearing the screen flickers
+        let mut out = String::new();
+        for (n, line) in lines.iter().enumerate() {
+            if self.lines.get(n) != Some(line) {
+                out += &format!("\x1B[{};1H{}", n + 1, line);
+            }
+        }
+        if lines.len() < self.lines.len() {
+            // Erase what a higher resolution left below
+            out += &format!("\x1B[{};1H\x1B[J", lines.len() + 1);
+        }
+        if !out.is_empty() {
+            let mut stdout = io::stdout();
+            // Drawing is best effort, a broken terminal shows up on input
+            let _ = stdout.write_all(out.as_bytes());
+            let _ = stdout.flush();
+        }
+        self.lines = lines;
+    }
+
+    fn status(&mut self, status: &Status) {
+        self.status = Some(*status);
+    }
+
+    fn message(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.read_keys();
+        self.open
+    }
+
+    fn poll_keys(&mut self, keypad: &mut dyn Keypad) {
+        let now = Instant::now();
+        for (k, held) in self.held.iter_mut().enumerate() {
+            match held {
+                Some(time) if now - *time < KEY_HOLD => keypad.press(k as u8),
+                Some(_) => {
+                    keypad.release(k as u8);
+                    *held = None;
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn poll_hotkey(&mut self) -> Option<Hotkey> {
+        if self.hotkeys.is_empty() {
+            None
+        } else {
+            Some(self.hotkeys.remove(0))
+        }
+    }
+}
+
+impl Drop for TerminalDisplay {
+    fn drop(&mut self) {
+        // Show the cursor and go back to the main screen
+        let mut out = io::stdout();
+        let _ = write!(out, "\x1B[0m\x1B[?25h\x1B[?1049l");
+        let _ = out.flush();
+    }
+}
+
+impl Default for TerminalDisplay {
+    fn default() -> Self {
+        Self::open().unwrap_or_else(|e| {
+            panic!("Could not set up the terminal {}", e);
+        })
+    }
+}
@@ -0,0 +1,113 @@
+//! Runs every ROM of `data/` headlessly and compares the final display with
+//! the golden PBM of `tests/golden/`
+//!
+//! Keys are pressed following `tests/golden/<ROM>.keys` when it exists, and
+//! the random numbers are always seeded with 0. After a change that is meant
+//! to alter the output, regenerate the goldens with
+//!
+//! ```text
+//! UPDATE_GOLDEN=1 cargo test --test golden
+//! ```
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_emulator::chip8::Chip8;
+use chip8_emulator::display::TextDisplay;
+use chip8_emulator::headless::{self, parse_script, Limit};
+use chip8_emulator::image::write_pbm;
+use chip8_emulator::quirks::Quirks;
+
+/// How long each ROM runs, 10 seconds at 60 Hz
+const FRAMES: u64 = 600;
+
+/// Seed of the random number generator
+const SEED: u64 = 0;
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Runs a ROM and renders its final display as a PBM, or the error that
+/// stopped it
+fn render(rom: &Path, keys: &Path) -> Result<String, String> {
+    let inputs = match fs::read_to_string(keys) {
+        Ok(script) => parse_script(&script)
+            .unwrap_or_else(|e| panic!("{}: {}", keys.display(), e)),
+        Err(_) => Vec::new(),
+    };
+
+    let mut chip8 = Chip8::<TextDisplay>::new(Quirks::default())
+        .seed(SEED)
+        .no_display();
+    chip8.load_rom(&fs::read(rom).unwrap()).unwrap();
+    let progress = headless::run(&mut chip8, Limit::Frames(FRAMES), &inputs);
+    if let Some(e) = progress.error {
+        return Err(format!(
+            "{} stopped at frame {}: {}",
+            rom.display(),
+            progress.frames,
+            e
+        ));
+    }
+
+    let mut pbm = Vec::new();
+    write_pbm(&chip8.frame, &mut pbm).unwrap();
+    Ok(String::from_utf8(pbm).unwrap())
+}
+
+#[test]
+fn golden_framebuffers() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let goldens = root().join("tests").join("golden");
+
+    // The options of the emulator next to the ROMs don't change the frames
+    let mut roms: Vec<PathBuf> = fs::read_dir(root().join("data"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() != Some("cfg".as_ref()))
+        .collect();
+    roms.sort();
+    assert!(!roms.is_empty());
+
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let name = rom.file_name().unwrap().to_string_lossy();
+        let golden = goldens.join(format!("{}.pbm", name));
+        let actual = match render(rom, &goldens.join(format!("{}.keys", name)))
+        {
+            Ok(actual) => actual,
+            Err(e) => {
+                failures.push(e);
+                continue;
+            }
+        };
+
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        match fs::read_to_string(&golden) {
+            Ok(expected) if expected == actual => {}
+            Ok(_) => {
+                // Keep the output around to compare it with the golden
+                let path = env::temp_dir().join(format!("{}.actual.pbm", name));
+                fs::write(&path, &actual).unwrap();
+                failures.push(format!(
+                    "{} differs from {}, got {}",
+                    name,
+                    golden.display(),
+                    path.display()
+                ));
+            }
+            Err(_) => failures.push(format!("{} is missing", golden.display())),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\nRun with UPDATE_GOLDEN=1 to regenerate the goldens",
+        failures.join("\n")
+    );
+}